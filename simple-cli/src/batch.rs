@@ -0,0 +1,272 @@
+use crate::calculator::RpnCalculator;
+use crate::format::Format;
+use crate::lexer;
+use crate::script::{Line, Script};
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Write};
+use std::mem;
+use std::sync::mpsc::sync_channel;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// --jobs と --stats の実装. 読み込み､評価､書き出しを別のスレッドにして行を流しながら処理する
+//
+//   読み込み(呼び出し元) --チャンク--> 評価(計算機1つにつき1スレッド) --結果--> 書き出し(入力順に並べ直す)
+//
+// 処理中のチャンクは 計算機の数 * CHUNKS_PER_JOB 個までなので､入力がどれだけ大きくても使うメモリは一定
+// ※ independentの場合は行ごとに別の計算機で評価されうるので､変数の束縛はエラーにし､乱数の種は行ごとに決める
+
+const CHUNK_LINES: usize = 1024;
+const CHUNKS_PER_JOB: usize = 4;
+
+pub struct Options {
+    pub label: bool,
+    pub format: Format,
+    // 行どうしが独立している(行の順番とスレッドの割り当てに結果が左右されない)ことを求める
+    pub independent: bool,
+    pub seed: Option<u64>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Stats {
+    pub lines: usize,
+    // 評価に失敗した行の数
+    pub errors: usize,
+    // 開けなかった､読めなかった入力の数
+    pub failed: usize,
+    pub bytes: usize,
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = self.elapsed.as_secs_f64().max(1e-9);
+        write!(
+            f,
+            "{} lines ({} errors) in {:.3}s: {:.0} lines/s, {:.2} MB/s",
+            self.lines,
+            self.errors,
+            self.elapsed.as_secs_f64(),
+            self.lines as f64 / secs,
+            self.bytes as f64 / secs / 1e6
+        )
+    }
+}
+
+enum Item {
+    // 入力全体での通し番号と行
+    Line(u64, Line),
+    Failure(String),
+}
+
+// 1行分の出力. stdoutは改行を含まない
+#[derive(Default)]
+struct Output {
+    stdout: Option<String>,
+    stderr: Option<String>,
+    error: bool,
+    failed: bool,
+}
+
+pub fn run<I, W, E>(inputs: I, calcurators: Vec<RpnCalculator>, opts: &Options, out: W, err: E) -> Result<Stats>
+where
+    I: Iterator<Item = Result<Script>>,
+    W: Write + Send,
+    E: Write + Send,
+{
+    let start = Instant::now();
+    let capacity = calcurators.len().max(1) * CHUNKS_PER_JOB;
+    // 書き出しが終わったチャンクの分だけ次のチャンクを読み込めるようにする
+    let (ticket_tx, ticket_rx) = sync_channel::<()>(capacity);
+    for _ in 0..capacity {
+        ticket_tx.send(())?;
+    }
+    let (chunk_tx, chunk_rx) = sync_channel::<(usize, Vec<Item>)>(capacity);
+    let chunk_rx = Mutex::new(chunk_rx);
+    let (result_tx, result_rx) = sync_channel::<(usize, Vec<Output>)>(capacity);
+
+    thread::scope(|s| {
+        for calcurator in calcurators {
+            let chunk_rx = &chunk_rx;
+            let result_tx = result_tx.clone();
+            s.spawn(move || loop {
+                let next = chunk_rx.lock().unwrap().recv();
+                let Ok((index, items)) = next else { break };
+                let outputs = items.into_iter().map(|item| evaluate(item, &calcurator, opts)).collect();
+                if result_tx.send((index, outputs)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(result_tx);
+
+        let writer = s.spawn(move || -> io::Result<Stats> {
+            let (mut out, mut err) = (out, err);
+            let mut stats = Stats::default();
+            let mut pending = BTreeMap::new();
+            let mut next = 0;
+            for (index, outputs) in result_rx {
+                pending.insert(index, outputs);
+                while let Some(outputs) = pending.remove(&next) {
+                    for output in outputs {
+                        if let Some(text) = output.stdout {
+                            writeln!(out, "{}", text)?;
+                        }
+                        if let Some(text) = output.stderr {
+                            // 結果とエラーの順番が入れ替わって見えないよう､先に標準出力を流す
+                            out.flush()?;
+                            writeln!(err, "{}", text)?;
+                        }
+                        stats.lines += usize::from(!output.failed);
+                        stats.errors += usize::from(output.error);
+                        stats.failed += usize::from(output.failed);
+                    }
+                    next += 1;
+                    let _ = ticket_tx.send(());
+                }
+            }
+            out.flush()?;
+            Ok(stats)
+        });
+
+        let mut bytes = 0;
+        let mut number = 0;
+        let mut index = 0;
+        let mut chunk = Vec::with_capacity(CHUNK_LINES);
+        let mut send = |chunk: Vec<Item>| {
+            // 書き出し側が止まった(出力先が閉じられたなど)ら読み込みもやめる
+            let ok = ticket_rx.recv().is_ok() && chunk_tx.send((index, chunk)).is_ok();
+            index += 1;
+            ok
+        };
+        'read: for script in inputs {
+            let lines: Box<dyn Iterator<Item = Result<Line>>> = match script {
+                Ok(script) => Box::new(script),
+                Err(e) => Box::new(std::iter::once(Err(e))),
+            };
+            for line in lines {
+                chunk.push(match line {
+                    Ok(line) => {
+                        bytes += line.text.len() + 1;
+                        number += 1;
+                        Item::Line(number, line)
+                    }
+                    Err(e) => Item::Failure(format!("Error: {:#}", e)),
+                });
+                if chunk.len() == CHUNK_LINES && !send(mem::take(&mut chunk)) {
+                    break 'read;
+                }
+            }
+        }
+        if !chunk.is_empty() {
+            send(chunk);
+        }
+        drop(chunk_tx);
+
+        let mut stats = writer.join().unwrap()?;
+        stats.bytes = bytes;
+        stats.elapsed = start.elapsed();
+        Ok(stats)
+    })
+}
+
+fn evaluate(item: Item, calcurator: &RpnCalculator, opts: &Options) -> Output {
+    let (number, line) = match item {
+        Item::Line(number, line) => (number, line),
+        Item::Failure(message) => {
+            return Output {
+                stderr: Some(message),
+                failed: true,
+                ..Output::default()
+            }
+        }
+    };
+    let label = if opts.label {
+        format!("{}:{}: ", line.source, line.number)
+    } else {
+        String::new()
+    };
+    if opts.independent && calcurator.has_binding(&line.text) {
+        return Output {
+            stdout: Some(format!("{}variable bindings are not allowed with --jobs", label)),
+            error: true,
+            ..Output::default()
+        };
+    }
+    if let (true, Some(seed)) = (opts.independent, opts.seed) {
+        calcurator.reseed(seed.wrapping_add(number));
+    }
+    // runと同じ表示にする
+    match calcurator.eval_line(&line.text) {
+        Ok(values) if values.is_empty() => Output {
+            stdout: opts.label.then_some(label),
+            ..Output::default()
+        },
+        Ok(values) => Output {
+            stdout: Some(format!("{}{}", label, opts.format.values(&values))),
+            ..Output::default()
+        },
+        Err(e) => Output {
+            stdout: Some(format!("{}{}", label, opts.format.error(&e))),
+            stderr: e
+                .pos()
+                .and_then(|pos| lexer::span(&line.text, pos, opts.format.numbers))
+                .map(|(col, len)| format!("  {}\n  {}{}", line.text, " ".repeat(col - 1), "^".repeat(len))),
+            error: true,
+            ..Output::default()
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn batch(input: &str, jobs: usize, independent: bool) -> (String, String, Stats) {
+        let calcurators = (0..jobs).map(|_| RpnCalculator::new(false)).collect();
+        let opts = Options {
+            label: false,
+            format: Format::default(),
+            independent,
+            seed: Some(7),
+        };
+        let script = Script::new(Box::new(Cursor::new(input.to_string())), "<test>");
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        let stats = run(std::iter::once(Ok(script)), calcurators, &opts, &mut out, &mut err).unwrap();
+        (String::from_utf8(out).unwrap(), String::from_utf8(err).unwrap(), stats)
+    }
+
+    #[test]
+    fn test_order() {
+        // チャンクをまたぐ長さにして､入力順に並ぶことを確かめる
+        let input = (0..5000)
+            .map(|i| if i % 7 == 0 { format!("{} 0 /", i) } else { format!("1 {} range sum {} +", i % 50 + 2, i) })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let (out1, err1, stats1) = batch(&input, 1, true);
+        let (out4, err4, stats4) = batch(&input, 4, true);
+        assert_eq!(out1, out4);
+        assert_eq!(err1, err4);
+        assert_eq!(out4.lines().count(), 5000);
+        assert_eq!(out4.lines().nth(7).unwrap(), "division by zero at 3");
+        assert_eq!((stats4.lines, stats4.errors), (5000, 715));
+        assert_eq!((stats1.lines, stats1.bytes), (stats4.lines, stats4.bytes));
+    }
+
+    #[test]
+    fn test_independent() {
+        let input = "3 =x\n1 2 +\nrand\n1 6 randint\n";
+        let (out1, _, _) = batch(input, 1, true);
+        let (out3, _, _) = batch(input, 3, true);
+        assert_eq!(out1, out3);
+        assert!(out1.starts_with("variable bindings are not allowed with --jobs\n3\n"), "{}", out1);
+        // 1スレッドで行の独立性を求めない場合は､束縛した変数を次の行で使える
+        let (out, _, stats) = batch("3 =x\nx 2 *\n", 1, false);
+        assert_eq!(out, "6\n");
+        assert_eq!((stats.lines, stats.errors), (2, 0));
+    }
+}
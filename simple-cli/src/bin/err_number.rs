@@ -0,0 +1,77 @@
+use clap::{ArgEnum, Parser};
+use rpncalc::exit::{Exit, Style};
+use rpncalc::number_source::{manual, with_anyhow, with_thiserror};
+use rpncalc::report::Report;
+use std::io::stdin;
+use std::path::PathBuf;
+
+// src/number_source.rs の3通りの実装を選んで実行し､エラーの出方を比べる
+//   cargo run --bin err_number -- --strategy thiserror assets/number/long.txt
+//   echo 12 | cargo run --bin err_number -- --strategy manual -
+// 終了コードはエラーの種類で変わる(exit.rs)
+#[derive(Parser, Debug)]
+#[clap(name = "err_number", about = "Compare error handling strategies on a number file")]
+struct Opts {
+    #[clap(long, arg_enum, default_value = "anyhow")]
+    strategy: Strategy,
+
+    // エラーを1行のJSONで出す
+    #[clap(long)]
+    json: bool,
+
+    // 原因を番号付きで並べて出す(RUST_BACKTRACEを設定するとバックトレースも)
+    #[clap(short, long, conflicts_with = "json")]
+    verbose: bool,
+
+    // "-" は標準入力を表す
+    #[clap(name = "FILE", default_value = "assets/number.txt")]
+    input: PathBuf,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug)]
+enum Strategy {
+    Manual,
+    Thiserror,
+    Anyhow,
+}
+
+// エラーの型は実装ごとに違うので､Reportにそろえて返す
+fn run(opts: &Opts) -> Result<i32, Report> {
+    let from_stdin = opts.input.to_str() == Some("-");
+    match opts.strategy {
+        Strategy::Manual => {
+            let res = if from_stdin {
+                manual::from_reader(stdin())
+            } else {
+                manual::from_path(&opts.input)
+            };
+            res.map_err(|e| Report::new(&e))
+        }
+        Strategy::Thiserror => {
+            let res = if from_stdin {
+                with_thiserror::from_reader(stdin(), "-")
+            } else {
+                with_thiserror::from_path(&opts.input)
+            };
+            res.map_err(|e| Report::new(&e))
+        }
+        Strategy::Anyhow => {
+            let res = if from_stdin {
+                with_anyhow::from_reader(stdin(), "-")
+            } else {
+                with_anyhow::from_path(&opts.input)
+            };
+            Ok(res?)
+        }
+    }
+}
+
+fn main() -> Exit {
+    let opts = Opts::parse();
+    let style = match (opts.json, opts.verbose) {
+        (true, _) => Style::Json,
+        (_, true) => Style::Full,
+        _ => Style::Line,
+    };
+    Exit::new(run(&opts).map(|x| println!("{}", x)), style)
+}
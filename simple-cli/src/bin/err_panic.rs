@@ -1,46 +1,25 @@
-use std::fmt;
+use rpncalc::exit::{Exit, Style};
+use rpncalc::number_source::manual;
+use rpncalc::panic_boundary;
+use rpncalc::report::Report;
+use std::env;
+use std::path::Path;
 
-enum MyError {
-    Io(std::io::Error),
-    Num(std::num::ParseIntError),
-}
-impl fmt::Display for MyError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            MyError::Io(cause) => write!(f, "I/O Error:{}", cause),
-            MyError::Num(cause) => write!(f, "Parse Error:{}", cause),
+// エラーの列挙型を手で書く場合. 実装は src/number_source.rs の manual
+// mainがExitを返すと､エラーの種類に応じた終了コードで終わる(--json でJSON､--verbose で原因を全部表示)
+//
+// --panic を付けると読み込みの前にパニックする. パニックはpanic_boundaryでエラーに変わり､終了コード70で終わる
+//   cargo run --bin err_panic -- --panic                            Error: thread 'main' panicked at ...  (終了コード70)
+//   cargo run --profile release-abort --bin err_panic -- --panic    フックの表示の後にabortする(終了コード134)
+fn main() -> Exit {
+    panic_boundary::install_hook();
+    let panic = env::args().any(|a| a == "--panic");
+    let res = panic_boundary::catch(|| {
+        if panic {
+            let digits: Vec<u32> = Vec::new();
+            println!("{}", digits[0]);
         }
-    }
-}
-impl From<std::io::Error> for MyError {
-    fn from(cause: std::io::Error) -> Self {
-        Self::Io(cause)
-    }
-}
-impl From<std::num::ParseIntError> for MyError {
-    fn from(cause: std::num::ParseIntError) -> Self {
-        Self::Num(cause)
-    }
-}
-
-fn get_int_from_file() -> Result<i32, MyError> {
-    let path = "assets/number.txt";
-
-    let num_str = std::fs::read_to_string(path)
-        .map_err(MyError::from)?;
-
-    num_str
-        .trim()
-        .parse::<i32>()
-        // mapはOk(T)の場合のみ処理される
-        .map(|t| t * 2)
-        // map_errorはErr(E)の場合のみ処理される
-        .map_err(MyError::from)
-}
-
-fn main() {
-    match get_int_from_file() {
-        Ok(x) => println!("{}", x),
-        Err(e) => println!("{}", e),
-    }
+        manual::from_path(Path::new("assets/number.txt")).map_err(|e| Report::new(&e))
+    });
+    Exit::new(res.and_then(|res| res).map(|x| println!("{}", x)), Style::from_args())
 }
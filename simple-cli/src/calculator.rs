@@ -0,0 +1,1486 @@
+use crate::date::Date;
+use crate::error::CalcError;
+use crate::finance;
+use crate::interval::Interval;
+use crate::lexer::{self, Tok, Token};
+use crate::locale::Numbers;
+use crate::matrix::{self, Matrix, MatrixError};
+use crate::number::Number;
+use crate::parser::{self, Node, Term};
+use crate::random::Rng;
+use crate::stats;
+use crate::text;
+use crate::units::{Quantity, Unit, UnitError};
+use crate::value::Value;
+use clap::ArgEnum;
+use serde::Deserialize;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::mem::size_of;
+use std::time::{Duration, Instant};
+
+// 英字の名前を持つ組み込み演算子. 変数より優先される(集計演算子はstats側で判定する)
+const BUILTINS: [&str; 42] = [
+    "dup", "drop", "swap", "clear", "range", "call", "map", "filter", "fold", "dot", "cross", "matmul", "transpose", "det",
+    "inv", "solve", "rand", "randint", "normal", "choice", "sample", "fv", "pv", "pmt", "nper", "rate", "compound",
+    "continuous", "npv", "irr", "concat", "len", "upper", "lower", "substr", "fmt", "parse", "weekday", "business-days",
+    "eom", "lo", "hi",
+];
+
+// 1回の評価で使える資源の上限. Noneなら無制限
+// 信用できない式を評価する場合(serveなど)に設定する. 上限ごとに別の種類のエラーになる
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Limits {
+    // 評価するトークンの数(クォーテーションの中身を繰り返し評価した分も数える)
+    pub max_steps: Option<usize>,
+    // スタックに積める値の数. リストを作っている間やクォーテーションの中のスタックも同じ上限
+    pub max_stack: Option<usize>,
+    // クォーテーションとリストの入れ子の深さ. 変数に入れたクォーテーションの再帰も数える
+    pub max_depth: Option<usize>,
+    // 1行のトークンの数(括弧も含む)
+    pub max_tokens: Option<usize>,
+    // 数値リテラルとfmtの結果の桁数
+    pub max_digits: Option<usize>,
+    // 1回の評価で作ったリストと文字列の大きさの合計(バイト). Value::heap_sizeで数える
+    // eval_onで持ち越したスタックと､変数に入っている分も含める
+    // ※ 使い終わった値も数えるので､実際に使っているメモリより大きくなる
+    pub max_memory: Option<usize>,
+    pub max_time: Option<Duration>,
+}
+
+impl Limits {
+    // 指定の無い上限をdefaultsの値で補う
+    pub fn or(self, defaults: Limits) -> Limits {
+        Limits {
+            max_steps: self.max_steps.or(defaults.max_steps),
+            max_stack: self.max_stack.or(defaults.max_stack),
+            max_depth: self.max_depth.or(defaults.max_depth),
+            max_tokens: self.max_tokens.or(defaults.max_tokens),
+            max_digits: self.max_digits.or(defaults.max_digits),
+            max_memory: self.max_memory.or(defaults.max_memory),
+            max_time: self.max_time.or(defaults.max_time),
+        }
+    }
+}
+
+// 数値リテラルの扱い. autoは整数と小数を書いたとおりに扱う
+#[derive(ArgEnum, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum NumberMode {
+    Auto,
+    // 小数のリテラルをエラーにする
+    Int,
+    // 整数のリテラルも小数として扱う
+    Float,
+    // 小数のリテラルを誤差付きの区間として扱う(interval.rs)
+    Interval,
+}
+
+// 評価後のスタックをどう扱うか
+#[derive(ArgEnum, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StackPolicy {
+    // ちょうど1つ残らなければエラー
+    Strict,
+    // 先頭の値だけを返す
+    Top,
+    // 残った値をすべて返す
+    All,
+}
+
+pub struct RpnCalculator {
+    verbose: bool,
+    mode: NumberMode,
+    policy: StackPolicy,
+    // evalは&selfで呼べるようにしておきたいので､乱数の状態や変数はRefCellに入れる
+    rng: RefCell<Rng>,
+    vars: RefCell<HashMap<String, Value>>,
+    limits: Limits,
+    // 以下は評価ごとに数え直す
+    steps: Cell<usize>,
+    depth: Cell<usize>,
+    memory: Cell<usize>,
+    started: Cell<Instant>,
+    numbers: Numbers,
+}
+
+impl RpnCalculator {
+    pub fn new(verbose: bool) -> Self {
+        Self {
+            verbose,
+            mode: NumberMode::Auto,
+            policy: StackPolicy::Strict,
+            rng: RefCell::new(Rng::from_time()),
+            vars: RefCell::new(HashMap::new()),
+            limits: Limits::default(),
+            steps: Cell::new(0),
+            depth: Cell::new(0),
+            memory: Cell::new(0),
+            started: Cell::new(Instant::now()),
+            numbers: Numbers::C,
+        }
+    }
+
+    pub fn with_limits(self, limits: Limits) -> Self {
+        Self { limits, ..self }
+    }
+
+    pub fn with_mode(self, mode: NumberMode) -> Self {
+        Self { mode, ..self }
+    }
+
+    // eval_lineの結果に使う. evalは常に1つの値を返す
+    pub fn with_policy(self, policy: StackPolicy) -> Self {
+        Self { policy, ..self }
+    }
+
+    // 1,234.5 や 1.234,5 のような数値を読む(locale.rs)
+    pub fn with_numbers(self, numbers: Numbers) -> Self {
+        Self { numbers, ..self }
+    }
+
+    // 乱数の種を固定する. 同じ種なら同じ入力に対して同じ結果になる
+    pub fn with_seed(self, seed: u64) -> Self {
+        Self {
+            rng: RefCell::new(Rng::new(seed)),
+            ..self
+        }
+    }
+
+    // --jobs で行ごとに種を決め直す. どのスレッドで評価しても同じ行なら同じ結果になる
+    pub fn reseed(&self, seed: u64) {
+        self.rng.replace(Rng::new(seed));
+    }
+
+    // クォーテーションやリストの中も含めて変数を束縛する語があるか
+    pub fn has_binding(&self, formula: &str) -> bool {
+        fn walk(nodes: &[Node]) -> bool {
+            nodes.iter().any(|node| match &node.term {
+                Term::List(body) | Term::Quote(body) => walk(body),
+                term => is_binding(term),
+            })
+        }
+        self.parse(formula).is_ok_and(|program| walk(&program))
+    }
+
+    pub fn is_builtin(name: &str) -> bool {
+        BUILTINS.contains(&name) || stats::is_aggregate(name) || name.strip_prefix('n').is_some_and(stats::is_aggregate)
+    }
+
+    // 変数を束縛する. 式の中で名前が出てきたら値を積む
+    // ※ 演算子と同じ名前は演算子が優先され､単位と同じ名前は変数が優先される
+    pub fn set_var(&self, name: &str, value: Value) {
+        self.vars.borrow_mut().insert(name.to_string(), value);
+    }
+
+    pub fn eval(&self, formula: &str) -> Result<Value, CalcError> {
+        let mut stack = Vec::new();
+        self.eval_on(formula, &mut stack)?;
+
+        if stack.len() != 1 {
+            return Err(CalcError::InvalidSyntax);
+        }
+        Ok(stack.remove(0))
+    }
+
+    // 1行を評価し､スタックの扱いに従って表示する値を返す
+    // "3 =x" のように変数の束縛だけで値が残らない行は空を返す
+    pub fn eval_line(&self, formula: &str) -> Result<Vec<Value>, CalcError> {
+        let mut stack = Vec::new();
+        self.eval_on(formula, &mut stack)?;
+
+        if stack.is_empty() && self.parse(formula)?.iter().any(|node| is_binding(&node.term)) {
+            return Ok(stack);
+        }
+        match (self.policy, stack.len()) {
+            (_, 0) => Err(CalcError::InvalidSyntax),
+            (StackPolicy::Strict, 1) | (StackPolicy::All, _) => Ok(stack),
+            (StackPolicy::Strict, _) => Err(CalcError::InvalidSyntax),
+            (StackPolicy::Top, _) => Ok(stack.split_off(stack.len() - 1)),
+        }
+    }
+
+    // 呼び出し側のスタックの上で評価する. serveのように行をまたいでスタックを持ち越す場合に使う
+    // エラーになった場合はスタックを評価前の状態に戻す
+    pub fn eval_on(&self, formula: &str, stack: &mut Vec<Value>) -> Result<(), CalcError> {
+        let program = self.parse(formula)?;
+        self.steps.set(0);
+        self.depth.set(0);
+        // 前の行までに作って残っている値も数える(変数は行をまたいで残る)
+        let kept = stack.iter().chain(self.vars.borrow().values()).map(Value::heap_size).sum();
+        self.memory.set(kept);
+        self.started.set(Instant::now());
+        let saved = stack.clone();
+        if let Err(e) = self.eval_impl(&program, stack) {
+            *stack = saved;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    // 上限を超える式は構文解析する前に断る
+    fn parse(&self, formula: &str) -> Result<Vec<Node>, CalcError> {
+        let tokens = lexer::lex(formula, self.numbers)?;
+        self.check_tokens(formula, &tokens)?;
+        parser::parse_tokens(tokens)
+    }
+
+    // トークン数と数値リテラルの桁数､括弧の入れ子の深さの上限は評価を始める前に調べる
+    // ※ 構文解析も括弧ごとに再帰するので､深さは解析する前に数えないとスタックがあふれる
+    fn check_tokens(&self, formula: &str, tokens: &[Token]) -> Result<(), CalcError> {
+        if let Some(limit) = self.limits.max_tokens.filter(|&limit| tokens.len() > limit) {
+            return Err(CalcError::TokenLimitExceeded {
+                pos: tokens[limit].pos,
+                limit,
+            });
+        }
+        if let Some(limit) = self.limits.max_digits {
+            let chars = formula.chars().collect::<Vec<_>>();
+            for token in tokens {
+                if !matches!(token.tok, Tok::Num(_) | Tok::Quantity(..) | Tok::Interval(..)) {
+                    continue;
+                }
+                let text = &chars[token.col - 1..token.col - 1 + token.len];
+                if text.iter().filter(|c| c.is_ascii_digit()).count() > limit {
+                    return Err(CalcError::DigitLimitExceeded { pos: token.pos, limit });
+                }
+            }
+        }
+        if let Some(limit) = self.limits.max_depth {
+            // 一番外側が深さ1(eval_implと同じ数え方)
+            let mut depth: usize = 1;
+            for token in tokens {
+                match token.tok {
+                    Tok::Open(_) => depth += 1,
+                    Tok::Close(_) => depth = depth.saturating_sub(1),
+                    _ => continue,
+                }
+                if depth > limit {
+                    return Err(CalcError::DepthLimitExceeded { pos: token.pos, limit });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // リストや文字列を作る前に呼び､作った大きさの合計が上限を超えたらエラーにする
+    fn allocate(&self, bytes: usize, pos: usize) -> Result<(), CalcError> {
+        self.check_memory(bytes, pos)?;
+        self.memory.set(self.memory.get().saturating_add(bytes));
+        Ok(())
+    }
+
+    // bytesだけ使うと上限を超えるかを調べる(数えはしない)
+    fn check_memory(&self, bytes: usize, pos: usize) -> Result<(), CalcError> {
+        match self.limits.max_memory {
+            Some(limit) if self.memory.get().saturating_add(bytes) > limit => Err(CalcError::MemoryLimitExceeded { pos, limit }),
+            _ => Ok(()),
+        }
+    }
+
+    fn allocate_values(&self, count: usize, pos: usize) -> Result<(), CalcError> {
+        self.allocate(count.saturating_mul(size_of::<Value>()), pos)
+    }
+
+    // 作るリストの入れ子の深さも上限に含める
+    // ※ 変数に入れたリストを [x] =x のように包み直すと行をまたいでいくらでも深くなり､
+    //    値を複製したり捨てたりする時にスタックがあふれる
+    fn check_nesting(&self, values: &[Value], pos: usize) -> Result<(), CalcError> {
+        match self.limits.max_depth {
+            Some(limit) if 1 + values.iter().map(Value::depth).max().unwrap_or(0) > limit => {
+                Err(CalcError::DepthLimitExceeded { pos, limit })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // クォーテーションの中身を評価する時にも呼ばれるので､スタックは呼び出し元から受け取る
+    fn eval_impl(&self, program: &[Node], stack: &mut Vec<Value>) -> Result<(), CalcError> {
+        let depth = self.depth.get() + 1;
+        if let Some(limit) = self.limits.max_depth.filter(|&limit| depth > limit) {
+            let pos = program.first().map_or(0, |node| node.pos);
+            return Err(CalcError::DepthLimitExceeded { pos, limit });
+        }
+        self.depth.set(depth);
+        let res = self.eval_nodes(program, stack);
+        self.depth.set(depth - 1);
+        res
+    }
+
+    fn eval_nodes(&self, program: &[Node], stack: &mut Vec<Value>) -> Result<(), CalcError> {
+        for (i, node) in program.iter().enumerate() {
+            self.steps.set(self.steps.get() + 1);
+            if let Some(limit) = self.limits.max_steps.filter(|&limit| self.steps.get() > limit) {
+                return Err(CalcError::StepLimitExceeded { pos: node.pos, limit });
+            }
+            if let Some(limit) = self.limits.max_time.filter(|&limit| self.started.get().elapsed() > limit) {
+                return Err(CalcError::TimeLimitExceeded { pos: node.pos, limit });
+            }
+            match &node.term {
+                Term::Num(x) => stack.push(self.literal(*x, node.pos)?),
+                Term::Interval(x, r) => stack.push(Value::Interval(Interval::around(x.to_f64(), r.to_f64()))),
+                Term::Quote(body) => {
+                    let quote = Value::Quote(body.clone());
+                    self.allocate(quote.heap_size(), node.pos)?;
+                    stack.push(quote);
+                }
+                Term::List(body) => {
+                    let mut values = Vec::new();
+                    self.eval_impl(body, &mut values)?;
+                    self.check_nesting(&values, node.pos)?;
+                    self.allocate_values(values.len(), node.pos)?;
+                    stack.push(Value::List(values));
+                }
+                Term::Quantity(x, unit) => match Unit::lookup(unit) {
+                    Some(unit) => stack.push(Value::Quantity(Quantity::new(x.to_f64(), unit))),
+                    None => {
+                        return Err(CalcError::InvalidToken {
+                            pos: node.pos,
+                            token: node.to_string(),
+                        })
+                    }
+                },
+                Term::Date(date) => stack.push(Value::Date(*date)),
+                Term::Str(s) => {
+                    self.allocate(s.len(), node.pos)?;
+                    stack.push(Value::Str(s.clone()));
+                }
+                Term::Word(word) => self.apply(stack, word, node.pos)?,
+            }
+            if let Some(limit) = self.limits.max_stack.filter(|&limit| stack.len() > limit) {
+                return Err(CalcError::StackLimitExceeded { pos: node.pos, limit });
+            }
+
+            // verbose表示. 残りのトークンは以前と同じく逆順で表示する
+            if self.verbose {
+                let rest = program[i + 1..].iter().rev().collect::<Vec<_>>();
+                println!("{:?} {:?}", rest, stack);
+            }
+        }
+        Ok(())
+    }
+
+    fn literal(&self, x: Number, pos: usize) -> Result<Value, CalcError> {
+        match (self.mode, x) {
+            (NumberMode::Int, Number::Float(_)) => Err(CalcError::InvalidArgument {
+                pos,
+                reason: format!("float literal {} in int mode", x),
+            }),
+            (NumberMode::Float, Number::Int(x)) => Ok(Value::Num(Number::Float(x as f64))),
+            (NumberMode::Interval, Number::Float(x)) => Ok(Value::Interval(Interval::around(x, 0.0))),
+            _ => Ok(Value::Num(x)),
+        }
+    }
+
+    fn apply(&self, stack: &mut Vec<Value>, token: &str, pos: usize) -> Result<(), CalcError> {
+        match token {
+            "+" | "-" | "*" | "/" | "%" => {
+                let y = pop(stack, pos)?;
+                let x = pop(stack, pos)?;
+                // 区間モードでは整数どうしの割り算も切り捨てず､幅0の区間にして計算する
+                // ※ 整数の剰余は切り捨てを含まない正確な値なのでそのまま
+                let (x, y) = match (x, y) {
+                    (Value::Num(Number::Int(x)), Value::Num(Number::Int(y)))
+                        if self.mode == NumberMode::Interval && token == "/" =>
+                    {
+                        (Value::Interval(Interval::from_int(x)), Value::Interval(Interval::from_int(y)))
+                    }
+                    other => other,
+                };
+                stack.push(arithmetic(token, x, y, pos)?);
+            }
+            // 比較演算子. 真なら1､偽なら0を積む
+            "<" | ">" | "<=" | ">=" | "==" | "!=" => {
+                let y = pop(stack, pos)?;
+                let x = pop(stack, pos)?;
+                let ord = match (x, y) {
+                    (Value::Num(x), Value::Num(y)) => x.total_cmp(&y),
+                    (x, y) if is_interval(&x) || is_interval(&y) => {
+                        let (a, b) = (to_interval(&x, pos)?, to_interval(&y, pos)?);
+                        a.compare(b).ok_or_else(|| CalcError::InvalidArgument {
+                            pos,
+                            reason: format!("cannot compare overlapping intervals {} and {}", x, y),
+                        })?
+                    }
+                    (Value::Str(x), Value::Str(y)) => x.cmp(&y),
+                    (Value::Date(x), Value::Date(y)) => x.cmp(&y),
+                    (x, y) => to_quantity(&x, pos)?
+                        .compare(&to_quantity(&y, pos)?)
+                        .map_err(|e| unit_error(e, pos))?,
+                };
+                let res = match token {
+                    "<" => ord.is_lt(),
+                    ">" => ord.is_gt(),
+                    "<=" => ord.is_le(),
+                    ">=" => ord.is_ge(),
+                    "==" => ord.is_eq(),
+                    _ => ord.is_ne(),
+                };
+                stack.push(Value::Num(Number::Int(res as i64)));
+            }
+            // スタック操作
+            "dup" => {
+                let x = pop(stack, pos)?;
+                self.allocate(x.heap_size(), pos)?;
+                stack.extend([x.clone(), x]);
+            }
+            "drop" => {
+                pop(stack, pos)?;
+            }
+            "swap" => {
+                let y = pop(stack, pos)?;
+                let x = pop(stack, pos)?;
+                stack.extend([y, x]);
+            }
+            "clear" => stack.clear(),
+            // 集計演算子. sumならスタック全体(先頭がリストならそのリスト)､nsumならスタック先頭のN個が対象
+            name if stats::is_aggregate(name) => {
+                let values = match stack.last() {
+                    Some(Value::List(_)) => match pop(stack, pos)? {
+                        Value::List(values) => values,
+                        _ => unreachable!(),
+                    },
+                    _ => std::mem::take(stack),
+                };
+                let res = stats::aggregate(name, &to_numbers(&values, pos)?, pos)?;
+                stack.extend(res.into_iter().map(Value::Num));
+            }
+            name if name.strip_prefix('n').is_some_and(stats::is_aggregate) => {
+                let n = pop_count(stack, pos)?;
+                if n > stack.len() {
+                    return Err(CalcError::StackUnderflow { pos });
+                }
+                let values = stack.split_off(stack.len() - n);
+                let res = stats::aggregate(&name[1..], &to_numbers(&values, pos)?, pos)?;
+                stack.extend(res.into_iter().map(Value::Num));
+            }
+            // a b range => [a, a+1, ..., b-1]
+            "range" => {
+                let end = pop_int(stack, pos)?;
+                let start = pop_int(stack, pos)?;
+                self.allocate_values(end.saturating_sub(start).max(0) as usize, pos)?;
+                let values = (start..end).map(|x| Value::Num(Number::Int(x))).collect();
+                stack.push(Value::List(values));
+            }
+            // クォーテーションを使う高階演算子
+            "call" => {
+                let body = pop_quote(stack, pos)?;
+                self.eval_impl(&body, stack)?;
+            }
+            // [1 2 3] { 2 * } map => [2 4 6]
+            "map" => {
+                let body = pop_quote(stack, pos)?;
+                let list = pop_list(stack, pos)?;
+                let values = list
+                    .into_iter()
+                    .map(|x| self.call_block(&body, vec![x], pos))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.check_nesting(&values, pos)?;
+                self.allocate_values(values.len(), pos)?;
+                stack.push(Value::List(values));
+            }
+            // [1 -2 3] { 0 > } filter => [1 3]
+            "filter" => {
+                let body = pop_quote(stack, pos)?;
+                let list = pop_list(stack, pos)?;
+                let mut values = Vec::new();
+                for x in list {
+                    match self.call_block(&body, vec![x.clone()], pos)? {
+                        Value::Num(cond) if cond.is_zero() => {}
+                        Value::Num(_) => values.push(x),
+                        other => return Err(type_mismatch("number", &other, pos)),
+                    }
+                }
+                self.allocate_values(values.len(), pos)?;
+                stack.push(Value::List(values));
+            }
+            // [1 2 3] 0 { + } fold => 6
+            "fold" => {
+                let body = pop_quote(stack, pos)?;
+                let init = pop(stack, pos)?;
+                let list = pop_list(stack, pos)?;
+                let res = list
+                    .into_iter()
+                    .try_fold(init, |acc, x| self.call_block(&body, vec![acc, x], pos))?;
+                stack.push(res);
+            }
+            // 線形代数. [1 2 3] はベクトル､[[1 2] [3 4]] は行列として扱う
+            "dot" | "cross" => {
+                let b = pop_vector(stack, pos)?;
+                let a = pop_vector(stack, pos)?;
+                let res = if token == "dot" {
+                    Value::Num(Number::Float(matrix::dot(&a, &b).map_err(|e| matrix_error(e, pos))?))
+                } else {
+                    from_vector(matrix::cross(&a, &b).map_err(|e| matrix_error(e, pos))?)
+                };
+                self.allocate(res.heap_size(), pos)?;
+                stack.push(res);
+            }
+            "matmul" => {
+                let (b, b_is_vector) = pop_matrix(stack, pos)?;
+                let (a, _) = pop_matrix(stack, pos)?;
+                self.allocate_values(a.rows().saturating_mul(b.cols() + 1), pos)?;
+                let res = a.mul(&b).map_err(|e| matrix_error(e, pos))?;
+                stack.push(from_matrix(&res, b_is_vector));
+            }
+            "transpose" => {
+                let (a, _) = pop_matrix(stack, pos)?;
+                let res = from_matrix(&a.transpose(), false);
+                self.allocate(res.heap_size(), pos)?;
+                stack.push(res);
+            }
+            "det" => {
+                let (a, _) = pop_matrix(stack, pos)?;
+                let det = a.det().map_err(|e| matrix_error(e, pos))?;
+                stack.push(Value::Num(Number::Float(det)));
+            }
+            "inv" => {
+                let (a, _) = pop_matrix(stack, pos)?;
+                let res = from_matrix(&a.inv().map_err(|e| matrix_error(e, pos))?, false);
+                self.allocate(res.heap_size(), pos)?;
+                stack.push(res);
+            }
+            // A b solve => Ax = b の解x
+            "solve" => {
+                let (b, b_is_vector) = pop_matrix(stack, pos)?;
+                let (a, _) = pop_matrix(stack, pos)?;
+                let res = from_matrix(&a.solve(&b).map_err(|e| matrix_error(e, pos))?, b_is_vector);
+                self.allocate(res.heap_size(), pos)?;
+                stack.push(res);
+            }
+            // 乱数
+            "rand" => {
+                let x = self.rng.borrow_mut().next_f64();
+                stack.push(Value::Num(Number::Float(x)));
+            }
+            // a b randint => a以上b以下の整数
+            "randint" => {
+                let hi = pop_int(stack, pos)?;
+                let lo = pop_int(stack, pos)?;
+                if lo > hi {
+                    return Err(CalcError::InvalidArgument {
+                        pos,
+                        reason: format!("empty range {}..={}", lo, hi),
+                    });
+                }
+                let x = self.rng.borrow_mut().range(lo, hi);
+                stack.push(Value::Num(Number::Int(x)));
+            }
+            // mean stdev normal => 正規分布に従う乱数
+            "normal" => {
+                let stdev = pop_num(stack, pos)?.to_f64();
+                let mean = pop_num(stack, pos)?.to_f64();
+                let x = self.rng.borrow_mut().normal(mean, stdev);
+                stack.push(Value::Num(Number::Float(x)));
+            }
+            "choice" => {
+                let list = pop_list(stack, pos)?;
+                if list.is_empty() {
+                    return Err(CalcError::InvalidArgument {
+                        pos,
+                        reason: "cannot choose from an empty list".to_string(),
+                    });
+                }
+                let i = self.rng.borrow_mut().range(0, list.len() as i64 - 1);
+                stack.push(list[i as usize].clone());
+            }
+            // N { ... } sample => クォーテーションをN回評価し､[平均 標準偏差 5%点 95%点] を積む
+            "sample" => {
+                let body = pop_quote(stack, pos)?;
+                let n = pop_count(stack, pos)?;
+                if n < 2 {
+                    return Err(CalcError::InvalidArgument {
+                        pos,
+                        reason: format!("sample needs at least 2 runs but got {}", n),
+                    });
+                }
+                self.allocate(n.saturating_mul(size_of::<Number>()), pos)?;
+                let mut values = Vec::with_capacity(n);
+                for _ in 0..n {
+                    match self.call_block(&body, Vec::new(), pos)? {
+                        Value::Num(x) => values.push(x),
+                        other => return Err(type_mismatch("number", &other, pos)),
+                    }
+                }
+                let mut summary = stats::aggregate("mean", &values, pos)?;
+                summary.extend(stats::aggregate("stdev", &values, pos)?);
+                summary.push(Number::Float(stats::percentile(&values, 5.0)));
+                summary.push(Number::Float(stats::percentile(&values, 95.0)));
+                stack.push(Value::List(summary.into_iter().map(Value::Num).collect()));
+            }
+            // 貨幣の時間価値. 各演算子のスタック効果はfinance.rsの先頭を参照
+            // ※ pvやfvは単位(ピコボルト､フェムトボルト)より演算子を優先する
+            "fv" | "pv" | "pmt" | "nper" | "rate" | "compound" => {
+                let [a, b, c, d] = pop_floats::<4>(stack, pos)?;
+                let res = match token {
+                    "fv" => Ok(finance::fv(a, b, c, d)),
+                    "pv" => Ok(finance::pv(a, b, c, d)),
+                    "pmt" => finance::pmt(a, b, c, d),
+                    "nper" => finance::nper(a, b, c, d),
+                    "rate" => finance::rate(a, b, c, d),
+                    _ => finance::compound(a, b, c, d),
+                };
+                stack.push(finance_result(res, pos)?);
+            }
+            "continuous" => {
+                let [principal, rate, years] = pop_floats::<3>(stack, pos)?;
+                stack.push(Value::Num(Number::Float(finance::continuous(principal, rate, years))));
+            }
+            "npv" => {
+                let flows = pop_vector(stack, pos)?;
+                let rate = pop_num(stack, pos)?.to_f64();
+                stack.push(Value::Num(Number::Float(finance::npv(rate, &flows))));
+            }
+            "irr" => {
+                let flows = pop_vector(stack, pos)?;
+                stack.push(finance_result(finance::irr(&flows), pos)?);
+            }
+            // 文字列. 各演算子のスタック効果はtext.rsの先頭を参照
+            "concat" => {
+                let b = pop_str(stack, pos)?;
+                let a = pop_str(stack, pos)?;
+                self.allocate(a.len() + b.len(), pos)?;
+                stack.push(Value::Str(a + &b));
+            }
+            "len" => {
+                let n = match pop(stack, pos)? {
+                    Value::Str(s) => s.chars().count(),
+                    Value::List(values) => values.len(),
+                    other => return Err(type_mismatch("string or list", &other, pos)),
+                };
+                stack.push(Value::Num(Number::Int(n as i64)));
+            }
+            "upper" | "lower" => {
+                let s = pop_str(stack, pos)?;
+                let s = if token == "upper" { s.to_uppercase() } else { s.to_lowercase() };
+                self.allocate(s.len(), pos)?;
+                stack.push(Value::Str(s));
+            }
+            "substr" => {
+                let len = pop_int(stack, pos)?;
+                let start = pop_int(stack, pos)?;
+                let s = pop_str(stack, pos)?;
+                let res = text_result(text::substr(&s, start, len), pos)?;
+                self.allocate(res.heap_size(), pos)?;
+                stack.push(res);
+            }
+            // 12.5 2 fmt => "12.50"､ 12.5 "%8.3f" fmt => "  12.500"
+            "fmt" => {
+                let format = match pop(stack, pos)? {
+                    Value::Str(format) => format,
+                    Value::Num(Number::Int(precision)) if precision >= 0 => format!("%.{}f", precision),
+                    Value::Num(x) => {
+                        return Err(CalcError::InvalidArgument {
+                            pos,
+                            reason: format!("precision must be a non-negative integer but got {}", x),
+                        })
+                    }
+                    other => return Err(type_mismatch("string", &other, pos)),
+                };
+                let x = pop_num(stack, pos)?;
+                // 幅や精度に大きな数を書いた書式は結果を作る前に断る
+                // ※ 変換は1つだけなので､結果は書式の長さと幅か精度の大きい方を足した程度に収まる
+                let requested = text::requested_len(&format);
+                let too_long = |len: usize| self.limits.max_digits.filter(|&limit| len > limit);
+                if let Some(limit) = too_long(requested) {
+                    return Err(CalcError::DigitLimitExceeded { pos, limit });
+                }
+                self.check_memory(requested.saturating_add(format.len()), pos)?;
+                let s = text::printf(&format, x).map_err(|reason| CalcError::InvalidArgument { pos, reason })?;
+                if let Some(limit) = too_long(s.chars().filter(char::is_ascii_digit).count()) {
+                    return Err(CalcError::DigitLimitExceeded { pos, limit });
+                }
+                self.allocate(s.len(), pos)?;
+                stack.push(Value::Str(s));
+            }
+            "parse" => {
+                let s = pop_str(stack, pos)?;
+                let x = text::parse(&s).map_err(|reason| CalcError::InvalidArgument { pos, reason })?;
+                stack.push(Value::Num(x));
+            }
+            // 区間の端. 区間でない数値はそのまま返す
+            "lo" | "hi" => {
+                let x = pop(stack, pos)?;
+                let res = match x {
+                    Value::Num(_) => x,
+                    x => {
+                        let x = to_interval(&x, pos)?;
+                        Value::Num(Number::Float(if token == "lo" { x.lo } else { x.hi }))
+                    }
+                };
+                stack.push(res);
+            }
+            // 日付. 各演算子のスタック効果はdate.rsの先頭を参照
+            "weekday" => {
+                let date = pop_date(stack, pos)?;
+                stack.push(Value::Num(Number::Int(date.weekday() as i64)));
+            }
+            "eom" => {
+                let date = pop_date(stack, pos)?;
+                stack.push(Value::Date(date.end_of_month()));
+            }
+            "business-days" => {
+                let res = match pop(stack, pos)? {
+                    Value::Date(end) => {
+                        let start = pop_date(stack, pos)?;
+                        Value::Num(Number::Int(start.business_days_until(end)))
+                    }
+                    Value::Num(Number::Int(n)) => {
+                        let date = pop_date(stack, pos)?;
+                        Value::Date(date.add_business_days(n).ok_or(CalcError::Overflow { pos })?)
+                    }
+                    Value::Num(x) => {
+                        return Err(CalcError::InvalidArgument {
+                            pos,
+                            reason: format!("expected an integer but got {}", x),
+                        })
+                    }
+                    other => return Err(type_mismatch("number or date", &other, pos)),
+                };
+                stack.push(res);
+            }
+            // 5000 m km -> => 5 km
+            "->" => {
+                let unit = match pop(stack, pos)? {
+                    Value::Unit(unit) => unit,
+                    other => return Err(type_mismatch("unit", &other, pos)),
+                };
+                match pop(stack, pos)? {
+                    Value::Quantity(q) => {
+                        let q = q.convert(&unit).map_err(|e| unit_error(e, pos))?;
+                        stack.push(Value::Quantity(q));
+                    }
+                    other => return Err(type_mismatch("quantity", &other, pos)),
+                }
+            }
+            // 3 =x => xに3を束縛する
+            name if is_binding_word(name) => {
+                // 組み込み演算子と同じ名前の変数は参照できないので束縛させない
+                if Self::is_builtin(&name[1..]) {
+                    return Err(CalcError::InvalidArgument {
+                        pos,
+                        reason: format!("`{}` is a builtin operator", &name[1..]),
+                    });
+                }
+                let value = pop(stack, pos)?;
+                self.set_var(&name[1..], value);
+            }
+            // クォーテーションを束縛した変数は､名前を書くと実行される({ 2 * } =double)
+            name if self.vars.borrow().contains_key(name) => {
+                let value = self.vars.borrow()[name].clone();
+                match value {
+                    Value::Quote(body) => self.eval_impl(&body, stack)?,
+                    value => {
+                        self.allocate(value.heap_size(), pos)?;
+                        stack.push(value);
+                    }
+                }
+            }
+            // 演算子でも変数でもなければ単位として扱う
+            // 数値の後なら単位を付け(3 m)､そうでなければ単位そのものを積む(km ->)
+            _ => match Unit::lookup(token) {
+                Some(unit) => match stack.last() {
+                    Some(Value::Num(_)) => {
+                        let x = pop_num(stack, pos)?;
+                        stack.push(Value::Quantity(Quantity::new(x.to_f64(), unit)));
+                    }
+                    _ => stack.push(Value::Unit(unit)),
+                },
+                None => {
+                    return Err(CalcError::InvalidToken {
+                        pos,
+                        token: token.to_string(),
+                    })
+                }
+            },
+        }
+        Ok(())
+    }
+
+    // 引数だけを積んだスタックでクォーテーションを評価し､残った1つの値を返す
+    fn call_block(&self, body: &[Node], args: Vec<Value>, pos: usize) -> Result<Value, CalcError> {
+        let mut stack = args;
+        self.eval_impl(body, &mut stack)?;
+        if stack.len() != 1 {
+            return Err(CalcError::InvalidArgument {
+                pos,
+                reason: format!("block must leave exactly one value but left {}", stack.len()),
+            });
+        }
+        Ok(stack.remove(0))
+    }
+}
+
+fn type_mismatch(expected: &'static str, actual: &Value, pos: usize) -> CalcError {
+    CalcError::TypeMismatch {
+        pos,
+        expected,
+        actual: actual.type_name(),
+    }
+}
+
+fn is_binding_word(word: &str) -> bool {
+    word.strip_prefix('=').is_some_and(|name| {
+        name.starts_with(|c: char| c.is_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+    })
+}
+
+fn is_binding(term: &Term) -> bool {
+    matches!(term, Term::Word(word) if is_binding_word(word))
+}
+
+fn pop(stack: &mut Vec<Value>, pos: usize) -> Result<Value, CalcError> {
+    stack.pop().ok_or(CalcError::StackUnderflow { pos })
+}
+
+fn pop_num(stack: &mut Vec<Value>, pos: usize) -> Result<Number, CalcError> {
+    match pop(stack, pos)? {
+        Value::Num(x) => Ok(x),
+        other => Err(type_mismatch("number", &other, pos)),
+    }
+}
+
+fn pop_int(stack: &mut Vec<Value>, pos: usize) -> Result<i64, CalcError> {
+    match pop_num(stack, pos)? {
+        Number::Int(x) => Ok(x),
+        x => Err(CalcError::InvalidArgument {
+            pos,
+            reason: format!("expected an integer but got {}", x),
+        }),
+    }
+}
+
+fn pop_count(stack: &mut Vec<Value>, pos: usize) -> Result<usize, CalcError> {
+    match pop_int(stack, pos)? {
+        n if n >= 0 => Ok(n as usize),
+        n => Err(CalcError::InvalidArgument {
+            pos,
+            reason: format!("count must be a non-negative integer but got {}", n),
+        }),
+    }
+}
+
+fn pop_list(stack: &mut Vec<Value>, pos: usize) -> Result<Vec<Value>, CalcError> {
+    match pop(stack, pos)? {
+        Value::List(values) => Ok(values),
+        other => Err(type_mismatch("list", &other, pos)),
+    }
+}
+
+fn pop_quote(stack: &mut Vec<Value>, pos: usize) -> Result<Vec<Node>, CalcError> {
+    match pop(stack, pos)? {
+        Value::Quote(body) => Ok(body),
+        other => Err(type_mismatch("quote", &other, pos)),
+    }
+}
+
+fn pop_str(stack: &mut Vec<Value>, pos: usize) -> Result<String, CalcError> {
+    match pop(stack, pos)? {
+        Value::Str(s) => Ok(s),
+        other => Err(type_mismatch("string", &other, pos)),
+    }
+}
+
+fn pop_date(stack: &mut Vec<Value>, pos: usize) -> Result<Date, CalcError> {
+    match pop(stack, pos)? {
+        Value::Date(date) => Ok(date),
+        other => Err(type_mismatch("date", &other, pos)),
+    }
+}
+
+// N個の数値を積んだ順に取り出す
+fn pop_floats<const N: usize>(stack: &mut Vec<Value>, pos: usize) -> Result<[f64; N], CalcError> {
+    let mut values = [0.0; N];
+    for value in values.iter_mut().rev() {
+        *value = pop_num(stack, pos)?.to_f64();
+    }
+    Ok(values)
+}
+
+fn finance_result(res: Result<f64, String>, pos: usize) -> Result<Value, CalcError> {
+    res.map(|x| Value::Num(Number::Float(x)))
+        .map_err(|reason| CalcError::InvalidArgument { pos, reason })
+}
+
+fn text_result(res: Result<String, String>, pos: usize) -> Result<Value, CalcError> {
+    res.map(Value::Str).map_err(|reason| CalcError::InvalidArgument { pos, reason })
+}
+
+fn pop_vector(stack: &mut Vec<Value>, pos: usize) -> Result<Vec<f64>, CalcError> {
+    let values = pop_list(stack, pos)?;
+    Ok(to_numbers(&values, pos)?.into_iter().map(Number::to_f64).collect())
+}
+
+// リストのリストは行列､数値のリストは列ベクトルとして取り出す. 後者の場合はtrueを返す
+fn pop_matrix(stack: &mut Vec<Value>, pos: usize) -> Result<(Matrix, bool), CalcError> {
+    let values = pop_list(stack, pos)?;
+    if !values.is_empty() && values.iter().all(|v| matches!(v, Value::List(_))) {
+        let rows = values
+            .into_iter()
+            .map(|row| match row {
+                Value::List(row) => Ok(to_numbers(&row, pos)?.into_iter().map(Number::to_f64).collect()),
+                _ => unreachable!(),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let m = Matrix::from_rows(rows).map_err(|e| matrix_error(e, pos))?;
+        Ok((m, false))
+    } else {
+        let column = to_numbers(&values, pos)?.into_iter().map(Number::to_f64).collect::<Vec<_>>();
+        if column.is_empty() {
+            return Err(matrix_error(MatrixError::Shape("vector must not be empty".to_string()), pos));
+        }
+        Ok((Matrix::column(column), true))
+    }
+}
+
+fn from_vector(values: Vec<f64>) -> Value {
+    Value::List(values.into_iter().map(|x| Value::Num(Number::Float(x))).collect())
+}
+
+fn from_matrix(m: &Matrix, is_vector: bool) -> Value {
+    if is_vector {
+        from_vector(m.to_column())
+    } else {
+        Value::List(m.to_rows().into_iter().map(from_vector).collect())
+    }
+}
+
+fn matrix_error(e: MatrixError, pos: usize) -> CalcError {
+    match e {
+        MatrixError::Shape(reason) => CalcError::ShapeMismatch { pos, reason },
+        MatrixError::Singular => CalcError::SingularMatrix { pos },
+    }
+}
+
+fn to_numbers(values: &[Value], pos: usize) -> Result<Vec<Number>, CalcError> {
+    values
+        .iter()
+        .map(|value| match value {
+            Value::Num(x) => Ok(*x),
+            other => Err(type_mismatch("number", other, pos)),
+        })
+        .collect()
+}
+
+fn arithmetic(op: &str, x: Value, y: Value, pos: usize) -> Result<Value, CalcError> {
+    match (x, y) {
+        (Value::Num(x), Value::Num(y)) => number_arithmetic(op, x, y, pos).map(Value::Num),
+        (x, y) if is_interval(&x) || is_interval(&y) => {
+            let (a, b) = (to_interval(&x, pos)?, to_interval(&y, pos)?);
+            let res = match op {
+                "+" => a.add(b),
+                "-" => a.sub(b),
+                "*" => a.mul(b),
+                "/" => a.div(b).ok_or(CalcError::DivisionByZero { pos })?,
+                _ => {
+                    return Err(CalcError::InvalidArgument {
+                        pos,
+                        reason: format!("{} is not defined for intervals", op),
+                    })
+                }
+            };
+            Ok(Value::Interval(res))
+        }
+        // 日付と時間. 日付どうしの差は日数(d)になる
+        (Value::Date(date), Value::Quantity(q)) if op == "+" || op == "-" => {
+            let days = whole_days(&q, pos)?;
+            let days = if op == "-" { -days } else { days };
+            date.add_days(days).map(Value::Date).ok_or(CalcError::Overflow { pos })
+        }
+        (Value::Quantity(q), Value::Date(date)) if op == "+" => {
+            let days = whole_days(&q, pos)?;
+            date.add_days(days).map(Value::Date).ok_or(CalcError::Overflow { pos })
+        }
+        (Value::Date(x), Value::Date(y)) if op == "-" => {
+            let days = y.days_until(x) as f64;
+            Ok(Value::Quantity(Quantity::new(days, Unit::lookup("d").unwrap())))
+        }
+        (Value::Date(_), other) if op == "+" || op == "-" => Err(type_mismatch("duration", &other, pos)),
+        (Value::Unit(a), Value::Unit(b)) if op == "*" => a.mul(&b).map(Value::Unit).map_err(|e| unit_error(e, pos)),
+        (Value::Unit(a), Value::Unit(b)) if op == "/" => a.div(&b).map(Value::Unit).map_err(|e| unit_error(e, pos)),
+        // 単位が絡む場合は､数値や単位もQuantityに揃えてから計算する
+        (x, y) => {
+            let x = to_quantity(&x, pos)?;
+            let y = to_quantity(&y, pos)?;
+            let res = match op {
+                "+" => x.add(&y),
+                "-" => x.sub(&y),
+                "*" => x.mul(&y),
+                "/" => x.div(&y),
+                "%" => x.rem(&y),
+                _ => unreachable!("unknown operator {}", op),
+            };
+            Ok(from_quantity(res.map_err(|e| unit_error(e, pos))?))
+        }
+    }
+}
+
+fn is_interval(value: &Value) -> bool {
+    matches!(value, Value::Interval(_))
+}
+
+// 区間と数値を計算する場合は､数値を幅0の区間にする
+fn to_interval(value: &Value, pos: usize) -> Result<Interval, CalcError> {
+    match value {
+        Value::Num(Number::Int(x)) => Ok(Interval::from_int(*x)),
+        Value::Num(Number::Float(x)) => Ok(Interval::point(*x)),
+        Value::Interval(x) => Ok(*x),
+        other => Err(type_mismatch("number", other, pos)),
+    }
+}
+
+// 日付に足せるのは1日単位の時間だけ(2w は14日､36h はエラー)
+fn whole_days(q: &Quantity, pos: usize) -> Result<i64, CalcError> {
+    if !q.is_duration() {
+        return Err(CalcError::InvalidArgument {
+            pos,
+            reason: format!("expected a duration but got {}", q),
+        });
+    }
+    let days = q.si_value() / 86400.0;
+    if !days.is_finite() || days.fract().abs() > 1e-9 || days.abs() >= i64::MAX as f64 {
+        return Err(CalcError::InvalidArgument {
+            pos,
+            reason: format!("{} is not a whole number of days", q),
+        });
+    }
+    Ok(days.round() as i64)
+}
+
+fn to_quantity(value: &Value, pos: usize) -> Result<Quantity, CalcError> {
+    match value {
+        Value::Num(x) => Ok(Quantity::scalar(x.to_f64())),
+        Value::Quantity(q) => Ok(q.clone()),
+        Value::Unit(unit) => Ok(Quantity::new(1.0, unit.clone())),
+        other => Err(type_mismatch("number", other, pos)),
+    }
+}
+
+// m/mのように次元が無くなったら､ただの数値に戻す
+fn from_quantity(q: Quantity) -> Value {
+    if q.unit.is_dimensionless() {
+        Value::Num(Number::Float(q.si_value()))
+    } else {
+        Value::Quantity(q)
+    }
+}
+
+fn unit_error(e: UnitError, pos: usize) -> CalcError {
+    match e {
+        UnitError::DimensionMismatch(left, right) => CalcError::DimensionMismatch { pos, left, right },
+        UnitError::DivisionByZero => CalcError::DivisionByZero { pos },
+        UnitError::Overflow => CalcError::Overflow { pos },
+    }
+}
+
+fn number_arithmetic(op: &str, x: Number, y: Number, pos: usize) -> Result<Number, CalcError> {
+    if y.is_zero() && (op == "/" || op == "%") {
+        return Err(CalcError::DivisionByZero { pos });
+    }
+    // オーバーフローでpanicしないよう､checked_*で計算する
+    let res = match op {
+        "+" => x.checked_add(y),
+        "-" => x.checked_sub(y),
+        "*" => x.checked_mul(y),
+        "/" => x.checked_div(y),
+        "%" => x.checked_rem(y),
+        _ => unreachable!("unknown operator {}", op),
+    };
+    res.ok_or(CalcError::Overflow { pos })
+}
+
+// cfgアトリビュートはコンディショナル的な属性. ここではcargo testの時のみ有効になる
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ok() {
+        let calclulator = RpnCalculator::new(false);
+        assert_eq!(calclulator.eval("5").unwrap(), 5);
+        assert_eq!(calclulator.eval("50").unwrap(), 50);
+        assert_eq!(calclulator.eval("-50").unwrap(), -50);
+
+        assert_eq!(calclulator.eval("2 3 +").unwrap(), 5);
+        assert_eq!(calclulator.eval("2 3 -").unwrap(), -1);
+        assert_eq!(calclulator.eval("2 3 *").unwrap(), 6);
+        assert_eq!(calclulator.eval("2 3 /").unwrap(), 0);
+        assert_eq!(calclulator.eval("2 3 %").unwrap(), 2);
+    }
+
+    #[test]
+    fn test_ng() {
+        let calclulator = RpnCalculator::new(false);
+        assert!(calclulator.eval("").is_err());
+        assert!(calclulator.eval("1 1 1 +").is_err());
+        assert!(calclulator.eval("+ 1 1").is_err());
+
+    }
+
+    #[test]
+    fn test_error_kind() {
+        let calclulator = RpnCalculator::new(false);
+        assert_eq!(calclulator.eval("1 +").unwrap_err().kind(), "StackUnderflow");
+        assert_eq!(calclulator.eval("1 2 ?").unwrap_err().kind(), "InvalidToken");
+        assert_eq!(calclulator.eval("1 0 /").unwrap_err().kind(), "DivisionByZero");
+        assert_eq!(calclulator.eval("9223372036854775807 1 +").unwrap_err().kind(), "Overflow");
+        assert_eq!(calclulator.eval("1 2").unwrap_err().kind(), "InvalidSyntax");
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let calclulator = RpnCalculator::new(false);
+        assert_eq!(calclulator.eval("1 2 3 4 sum").unwrap(), 10);
+        assert_eq!(calclulator.eval("1 2 3 4 mean").unwrap(), 2.5);
+        assert_eq!(calclulator.eval("100 1 2 3 3 nsum +").unwrap(), 106);
+        assert_eq!(calclulator.eval("5 1 2 3 3 nmax *").unwrap(), 15);
+        assert_eq!(calclulator.eval("0 1 1 3 2 5 linreg swap 10 * +").unwrap(), 21.0);
+        assert_eq!(calclulator.eval("1.5 2 *").unwrap(), 3.0);
+        assert_eq!(calclulator.eval("1 2 3 nsum").unwrap_err().kind(), "StackUnderflow");
+        assert_eq!(calclulator.eval("1 2 -1 nsum").unwrap_err().kind(), "InvalidArgument");
+        assert_eq!(calclulator.eval("[1 2 3 4] sum").unwrap(), 10);
+    }
+
+    #[test]
+    fn test_list() {
+        let calclulator = RpnCalculator::new(false);
+        assert_eq!(calclulator.eval("[1 2 3]").unwrap().to_string(), "[1 2 3]");
+        assert_eq!(calclulator.eval("[1 2 + [4]]").unwrap().to_string(), "[3 [4]]");
+        assert_eq!(calclulator.eval("1 5 range").unwrap().to_string(), "[1 2 3 4]");
+        assert_eq!(calclulator.eval("[1 2 3] { 2 * } map").unwrap().to_string(), "[2 4 6]");
+        assert_eq!(calclulator.eval("[1 -2 3] { 0 > } filter").unwrap().to_string(), "[1 3]");
+        assert_eq!(calclulator.eval("[1 2 3] 0 { + } fold").unwrap(), 6);
+        assert_eq!(calclulator.eval("1 11 range { 2 % 0 == } filter { dup * } map sum").unwrap(), 220);
+        assert_eq!(calclulator.eval("2 { 3 * } call").unwrap(), 6);
+    }
+
+    #[test]
+    fn test_list_error() {
+        let calclulator = RpnCalculator::new(false);
+        assert_eq!(
+            calclulator.eval("[1 2] 1 +").unwrap_err(),
+            CalcError::TypeMismatch {
+                pos: 6,
+                expected: "number",
+                actual: "list"
+            }
+        );
+        assert_eq!(calclulator.eval("1 { 2 * } map").unwrap_err().kind(), "TypeMismatch");
+        assert_eq!(calclulator.eval("[1 2] { dup } map").unwrap_err().kind(), "InvalidArgument");
+        assert_eq!(calclulator.eval("[1 2").unwrap_err().kind(), "UnbalancedBracket");
+    }
+
+    #[test]
+    fn test_units() {
+        let calclulator = RpnCalculator::new(false);
+        assert_eq!(calclulator.eval("3 m 2 s /").unwrap().to_string(), "1.5 m/s");
+        assert_eq!(calclulator.eval("1 km 500 m +").unwrap().to_string(), "1.5 km");
+        assert_eq!(calclulator.eval("5000 m km ->").unwrap().to_string(), "5 km");
+        assert_eq!(calclulator.eval("2 h minute ->").unwrap().to_string(), "120 min");
+        assert_eq!(calclulator.eval(&format!("1 m{}", " m *".repeat(130))).unwrap_err().kind(), "Overflow");
+        assert_eq!(calclulator.eval("90 km h / m s / ->").unwrap().to_string(), "25 m/s");
+        assert_eq!(calclulator.eval("2 kg 3 m * 1 s s * / N ->").unwrap().to_string(), "6 N");
+        assert_eq!(calclulator.eval("6 m 2 m /").unwrap(), 3.0);
+        assert_eq!(calclulator.eval("1 km 999 m >").unwrap(), 1);
+        assert_eq!(
+            calclulator.eval("3 m 2 s +").unwrap_err(),
+            CalcError::DimensionMismatch {
+                pos: 5,
+                left: "m".into(),
+                right: "s".into()
+            }
+        );
+        assert_eq!(calclulator.eval("3 m 2 +").unwrap_err().kind(), "DimensionMismatch");
+        assert_eq!(calclulator.eval("3 m s ->").unwrap_err().kind(), "DimensionMismatch");
+    }
+
+    #[test]
+    fn test_matrix() {
+        let calclulator = RpnCalculator::new(false);
+        assert_eq!(calclulator.eval("[1 2 3] [4 5 6] dot").unwrap(), 32.0);
+        assert_eq!(calclulator.eval("[1 0 0] [0 1 0] cross").unwrap().to_string(), "[0 0 1]");
+        assert_eq!(calclulator.eval("[[1 2] [3 4]] [5 6] matmul").unwrap().to_string(), "[17 39]");
+        assert_eq!(
+            calclulator.eval("[[1 2] [3 4]] [[0 1] [1 0]] matmul").unwrap().to_string(),
+            "[[2 1] [4 3]]"
+        );
+        assert_eq!(calclulator.eval("[[1 2 3] [4 5 6]] transpose").unwrap().to_string(), "[[1 4] [2 5] [3 6]]");
+        assert_eq!(calclulator.eval("[[2 0] [0 4]] det").unwrap(), 8.0);
+        assert_eq!(calclulator.eval("[[2 0] [0 4]] inv").unwrap().to_string(), "[[0.5 0] [0 0.25]]");
+        assert_eq!(calclulator.eval("[[2 1] [1 3]] [3 5] solve").unwrap().to_string(), "[0.8 1.4]");
+        assert_eq!(
+            calclulator.eval("[[1 2] [3 4]] [1 2 3] matmul").unwrap_err(),
+            CalcError::ShapeMismatch {
+                pos: 16,
+                reason: "cannot multiply 2x2 by 3x1".into()
+            }
+        );
+        assert_eq!(calclulator.eval("[[1 2] [3]] det").unwrap_err().kind(), "ShapeMismatch");
+        assert_eq!(calclulator.eval("[[1 2] [2 4]] inv").unwrap_err().kind(), "SingularMatrix");
+    }
+
+    #[test]
+    fn test_random() {
+        let a = RpnCalculator::new(false).with_seed(42);
+        let b = RpnCalculator::new(false).with_seed(42);
+        for formula in ["rand", "1 6 randint", "10 2 normal", "[1 2 3] choice", "100 { rand } sample"] {
+            assert_eq!(a.eval(formula).unwrap(), b.eval(formula).unwrap(), "{}", formula);
+        }
+        let x = a.eval("1 6 randint").unwrap();
+        assert!((1..=6).any(|i| x == i));
+        let summary = a.eval("2000 { 10 2 normal } sample").unwrap().to_string();
+        let values = summary
+            .trim_matches(['[', ']'])
+            .split(' ')
+            .map(|x| x.parse::<f64>().unwrap())
+            .collect::<Vec<_>>();
+        assert!((values[0] - 10.0).abs() < 0.2 && (values[1] - 2.0).abs() < 0.2, "{}", summary);
+        assert!(values[2] < values[0] && values[0] < values[3], "{}", summary);
+        assert_eq!(a.eval("6 1 randint").unwrap_err().kind(), "InvalidArgument");
+        assert_eq!(a.eval("[] choice").unwrap_err().kind(), "InvalidArgument");
+    }
+
+    #[test]
+    fn test_finance() {
+        let calclulator = RpnCalculator::new(false);
+        assert_eq!(calclulator.eval("0 10 -100 -1000 fv").unwrap(), 2000.0);
+        assert_eq!(calclulator.eval("0 12 -100 0 pv").unwrap(), 1200.0);
+        assert_eq!(calclulator.eval("0 -100 -1000 10000 nper").unwrap(), 90.0);
+        assert_eq!(calclulator.eval("0.5 [3 9] npv").unwrap(), 6.0);
+        // pvはピコボルトではなく演算子として扱う
+        assert_eq!(calclulator.eval("1 pv").unwrap_err(), CalcError::StackUnderflow { pos: 2 });
+        assert_eq!(
+            calclulator.eval("[1 2] irr").unwrap_err(),
+            CalcError::InvalidArgument {
+                pos: 5,
+                reason: "irr needs both positive and negative cash flows".into()
+            }
+        );
+    }
+
+    #[test]
+    fn test_vars() {
+        let calclulator = RpnCalculator::new(false);
+        assert!(matches!(calclulator.eval("x 1 +").unwrap_err(), CalcError::InvalidToken { .. }));
+        calclulator.set_var("x", Value::Num(Number::Int(2)));
+        calclulator.set_var("m", Value::Num(Number::Int(10)));
+        assert_eq!(calclulator.eval("x 1 +").unwrap(), 3);
+        // 単位より変数が優先される
+        assert_eq!(calclulator.eval("m x *").unwrap(), 20);
+    }
+
+    #[test]
+    fn test_numbers() {
+        let calclulator = RpnCalculator::new(false).with_numbers(Numbers::De);
+        assert_eq!(calclulator.eval("1.000,5 2 *").unwrap().to_string(), "2001");
+        assert_eq!(calclulator.eval("0,5+-0,25 hi 1 <").unwrap().to_string(), "1");
+        assert_eq!(calclulator.eval("1.5 2 +").unwrap_err().kind(), "InvalidToken");
+    }
+
+    #[test]
+    fn test_limits() {
+        let kind = |limits: Limits, formula: &str| {
+            let calclulator = RpnCalculator::new(false).with_limits(limits);
+            calclulator.eval(formula).map_err(|e| e.kind())
+        };
+        let none = Limits::default();
+        let limits = |f: fn(&mut Limits)| {
+            let mut limits = Limits::default();
+            f(&mut limits);
+            limits
+        };
+        let stack = limits(|l| l.max_stack = Some(3));
+        assert!(kind(stack, "1 2 3 + +").is_ok());
+        assert_eq!(kind(stack, "1 2 3 4 + + +"), Err("StackLimitExceeded"));
+        assert_eq!(kind(stack, "[1 2 3 4] len"), Err("StackLimitExceeded"));
+        // 変数に入れたクォーテーションが自分自身を呼ぶ
+        let depth = limits(|l| l.max_depth = Some(20));
+        assert_eq!(kind(depth, "{ f } =f f"), Err("DepthLimitExceeded"));
+        assert!(kind(depth, "[[[1]]] len").is_ok());
+        // 構文解析で再帰する前に断る
+        let nested = "[".repeat(300000);
+        assert_eq!(kind(limits(|l| l.max_depth = Some(10)), &nested), Err("DepthLimitExceeded"));
+        let balanced = format!("{}1{} len", "[".repeat(100000), "]".repeat(100000));
+        assert_eq!(kind(depth, &balanced), Err("DepthLimitExceeded"));
+        assert!(!RpnCalculator::new(false).with_limits(depth).has_binding(&nested));
+        // 変数を使って1行ずつリストを包み直しても上限より深くはならない
+        let calclulator = RpnCalculator::new(false).with_limits(depth);
+        calclulator.set_var("x", Value::Num(Number::Int(0)));
+        let wrapped = (0..30).map(|_| calclulator.eval_line("[x] =x").map_err(|e| e.kind())).collect::<Vec<_>>();
+        assert!(wrapped[..20].iter().all(Result::is_ok));
+        assert_eq!(wrapped[20], Err("DepthLimitExceeded"));
+        assert_eq!(calclulator.eval("[1] { drop x } map").unwrap_err().kind(), "DepthLimitExceeded");
+        let tokens = limits(|l| l.max_tokens = Some(4));
+        assert!(kind(tokens, "[1 2]").is_ok());
+        assert_eq!(kind(tokens, "1 2 + 3 +"), Err("TokenLimitExceeded"));
+        let digits = limits(|l| l.max_digits = Some(20));
+        assert!(kind(digits, "12345678901234567890 1 -").is_ok());
+        assert_eq!(kind(digits, "123456789012345678901.0"), Err("DigitLimitExceeded"));
+        assert_eq!(kind(digits, "1 \"%.100000000000f\" fmt"), Err("DigitLimitExceeded"));
+        assert_eq!(kind(digits, "1e300 \"%f\" fmt"), Err("DigitLimitExceeded"));
+        // rangeはリストを作る前に断る
+        let memory = limits(|l| l.max_memory = Some(1 << 20));
+        assert!(kind(memory, "0 1000 range sum").is_ok());
+        assert!(kind(none, "0 1000 range sum").is_ok());
+        assert_eq!(kind(memory, "0 1000000000000 range len"), Err("MemoryLimitExceeded"));
+        assert_eq!(kind(memory, "0 10000 range dup dup dup dup drop drop drop drop len"), Err("MemoryLimitExceeded"));
+        // 変数に入れた値も次の行で数える
+        let calclulator = RpnCalculator::new(false).with_limits(memory);
+        assert!(calclulator.eval_line("0 15000 range =a").is_ok());
+        assert_eq!(calclulator.eval_line("0 15000 range =b").unwrap_err().kind(), "MemoryLimitExceeded");
+        assert!(calclulator.eval_line("0 =a").is_ok());
+        assert!(calclulator.eval_line("0 15000 range =b").is_ok());
+        // fmtは桁数の上限が無くても結果を作る前に断る
+        assert_eq!(kind(memory, "1 \"%100000000000d\" fmt"), Err("MemoryLimitExceeded"));
+        assert_eq!(kind(memory, "1 \"%.100000000000f\" fmt"), Err("MemoryLimitExceeded"));
+        assert!(kind(memory, "1 \"%10d\" fmt").is_ok());
+        let time = limits(|l| l.max_time = Some(Duration::from_millis(50)));
+        assert_eq!(kind(time, "0 1000000 range { { 1 + } call } map len"), Err("TimeLimitExceeded"));
+    }
+
+    #[test]
+    fn test_session() {
+        let calclulator = RpnCalculator::new(false).with_limits(Limits {
+            max_steps: Some(100),
+            ..Limits::default()
+        });
+        assert_eq!(calclulator.eval_line("3 =x").unwrap(), Vec::<Value>::new());
+        assert_eq!(calclulator.eval_line("x 2 *").unwrap(), vec![Value::Num(Number::Int(6))]);
+        assert_eq!(calclulator.eval_line("{ dup * } =square 4 square").unwrap(), vec![Value::Num(Number::Int(16))]);
+        assert_eq!(calclulator.eval_line("1 2").unwrap_err(), CalcError::InvalidSyntax);
+        assert_eq!(calclulator.eval_line("1 =sum").unwrap_err().kind(), "InvalidArgument");
+        assert!(calclulator.has_binding("[1 { 2 =y } call]"));
+        assert!(!calclulator.has_binding("1 2 == \"=x\" swap"));
+
+        let mut stack = Vec::new();
+        calclulator.eval_on("1 2", &mut stack).unwrap();
+        calclulator.eval_on("+", &mut stack).unwrap();
+        assert_eq!(stack, vec![Value::Num(Number::Int(3))]);
+        // エラーになった行の途中経過は残らない
+        assert!(calclulator.eval_on("5 0 /", &mut stack).is_err());
+        assert_eq!(stack, vec![Value::Num(Number::Int(3))]);
+
+        assert_eq!(
+            calclulator.eval("1 200 range { 1 + } map sum").unwrap_err(),
+            CalcError::StepLimitExceeded { pos: 6, limit: 100 }
+        );
+        assert_eq!(calclulator.eval("1 20 range { 1 + } map sum").unwrap(), 209);
+    }
+
+    #[test]
+    fn test_mode_policy() {
+        let calclulator = RpnCalculator::new(false).with_mode(NumberMode::Float);
+        assert_eq!(calclulator.eval("7 2 /").unwrap().to_string(), "3.5");
+        let calclulator = RpnCalculator::new(false).with_mode(NumberMode::Int);
+        assert_eq!(calclulator.eval("7 2 /").unwrap(), 3);
+        assert_eq!(calclulator.eval("1 2.5 +").unwrap_err().kind(), "InvalidArgument");
+
+        let calclulator = RpnCalculator::new(false).with_policy(StackPolicy::Top);
+        assert_eq!(calclulator.eval_line("1 2 3").unwrap(), vec![Value::Num(Number::Int(3))]);
+        let calclulator = RpnCalculator::new(false).with_policy(StackPolicy::All);
+        assert_eq!(calclulator.eval_line("1 2").unwrap().len(), 2);
+        assert_eq!(calclulator.eval_line("1 drop").unwrap_err(), CalcError::InvalidSyntax);
+    }
+
+    #[test]
+    fn test_unspaced() {
+        let calclulator = RpnCalculator::new(false);
+        assert_eq!(calclulator.eval("2 3+").unwrap(), 5);
+        assert_eq!(calclulator.eval("1 2 3++").unwrap(), 6);
+        assert_eq!(calclulator.eval("10 -3-").unwrap(), 13);
+        assert_eq!(calclulator.eval("1 ( this is ignored ) 2+").unwrap(), 3);
+        // 数値の直後の単位と､単独のmin(集計演算子)
+        assert_eq!(calclulator.eval("90min h ->").unwrap().to_string(), "1.5 h");
+        assert_eq!(calclulator.eval("3 1 2 min").unwrap(), 1);
+        assert_eq!(
+            calclulator.eval("1 0/").unwrap_err(),
+            CalcError::DivisionByZero { pos: 3 }
+        );
+        assert_eq!(calclulator.eval("5xyz").unwrap_err().kind(), "InvalidToken");
+        assert_eq!(calclulator.eval("\"abc").unwrap_err().kind(), "UnterminatedString");
+    }
+
+    #[test]
+    fn test_strings() {
+        let calclulator = RpnCalculator::new(false);
+        assert_eq!(calclulator.eval("\"hello\" \" world\" concat").unwrap().to_string(), "hello world");
+        assert_eq!(calclulator.eval("\"héllo\" len").unwrap(), 5);
+        assert_eq!(calclulator.eval("[1 2 3] len").unwrap(), 3);
+        assert_eq!(calclulator.eval("\"abc\" upper 1 2 substr").unwrap().to_string(), "BC");
+        assert_eq!(calclulator.eval("12.5 2 fmt \"USD \" swap concat").unwrap().to_string(), "USD 12.50");
+        assert_eq!(calclulator.eval("42 \"%05d\" fmt").unwrap().to_string(), "00042");
+        assert_eq!(calclulator.eval("\" 2.5 \" parse 2 *").unwrap(), 5.0);
+        assert_eq!(calclulator.eval("\"a\" \"b\" <").unwrap(), 1);
+        assert_eq!(calclulator.eval("[\"a\" 1]").unwrap().to_string(), "[\"a\" 1]");
+        assert_eq!(
+            calclulator.eval("\"a\" 1 concat").unwrap_err(),
+            CalcError::TypeMismatch {
+                pos: 3,
+                expected: "string",
+                actual: "number"
+            }
+        );
+        assert_eq!(
+            calclulator.eval("\"a\" 1 +").unwrap_err(),
+            CalcError::TypeMismatch {
+                pos: 3,
+                expected: "number",
+                actual: "string"
+            }
+        );
+        assert_eq!(calclulator.eval("\"%d\" fmt").unwrap_err().kind(), "StackUnderflow");
+        assert_eq!(calclulator.eval("1.5 \"%d\" fmt").unwrap_err().kind(), "InvalidArgument");
+        assert_eq!(calclulator.eval("\"x1\" parse").unwrap_err().kind(), "InvalidArgument");
+    }
+
+    #[test]
+    fn test_dates() {
+        let calclulator = RpnCalculator::new(false);
+        assert_eq!(calclulator.eval("2026-10-18 3d +").unwrap().to_string(), "2026-10-21");
+        assert_eq!(calclulator.eval("2w 2026-12-25 +").unwrap().to_string(), "2027-01-08");
+        assert_eq!(calclulator.eval("2026-03-01 1d -").unwrap().to_string(), "2026-02-28");
+        assert_eq!(calclulator.eval("2026-12-25 2026-10-18 -").unwrap().to_string(), "68 d");
+        assert_eq!(calclulator.eval("2026-12-25 2026-10-18 - w ->").unwrap().to_string(), "9.714285714285714 w");
+        assert_eq!(calclulator.eval("2026-10-18 weekday").unwrap(), 7);
+        assert_eq!(calclulator.eval("2026-10-18 45 business-days").unwrap().to_string(), "2026-12-18");
+        assert_eq!(calclulator.eval("2026-10-16 2026-10-23 business-days").unwrap(), 5);
+        assert_eq!(calclulator.eval("2028-02-03 eom").unwrap().to_string(), "2028-02-29");
+        assert_eq!(calclulator.eval("2026-10-18 2026-10-19 <").unwrap(), 1);
+        assert_eq!(calclulator.eval("2026-10-18 36h +").unwrap_err().kind(), "InvalidArgument");
+        assert_eq!(calclulator.eval("2026-10-18 3m +").unwrap_err().kind(), "InvalidArgument");
+        assert_eq!(
+            calclulator.eval("2026-10-18 3 +").unwrap_err(),
+            CalcError::TypeMismatch {
+                pos: 3,
+                expected: "duration",
+                actual: "number"
+            }
+        );
+        assert_eq!(calclulator.eval("2026-10-18 2026-10-19 +").unwrap_err().kind(), "TypeMismatch");
+        assert_eq!(calclulator.eval("9999-12-31 1d +").unwrap_err().kind(), "Overflow");
+        assert_eq!(calclulator.eval("0000-01-01 1 business-days").unwrap().to_string(), "0000-01-03");
+    }
+
+    #[test]
+    fn test_interval() {
+        let calclulator = RpnCalculator::new(false);
+        assert_eq!(calclulator.eval("3.2+-0.1 2 *").unwrap().to_string(), "6.40+-0.20");
+        assert_eq!(calclulator.eval("10+-1 2+-0.5 /").unwrap().to_string(), "5.5+-1.9");
+        assert_eq!(calclulator.eval("1 -1+-2 /").unwrap().to_string(), "[-inf, inf]");
+        assert_eq!(calclulator.eval("1+-1 0 /").unwrap_err().kind(), "DivisionByZero");
+        assert_eq!(calclulator.eval("3+-1 5 <").unwrap(), 1);
+        assert_eq!(calclulator.eval("3+-1 3.5 <").unwrap_err().kind(), "InvalidArgument");
+        assert_eq!(calclulator.eval("3+-1 2 %").unwrap_err().kind(), "InvalidArgument");
+        assert!(matches!(calclulator.eval("3+-1 lo").unwrap(), Value::Num(Number::Float(x)) if x < 2.0 && x > 1.99));
+
+        let calclulator = RpnCalculator::new(false).with_mode(NumberMode::Interval);
+        let sum = calclulator.eval("0.1 0.2 +").unwrap();
+        assert!(matches!(sum, Value::Interval(x) if x.contains(0.3)), "{}", sum);
+        let quotient = calclulator.eval("7 2 /").unwrap();
+        assert!(matches!(quotient, Value::Interval(x) if x.contains(3.5)), "{}", quotient);
+        assert_eq!(calclulator.eval("7 0 /").unwrap_err().kind(), "DivisionByZero");
+        assert_eq!(calclulator.eval("7 2 %").unwrap(), 1);
+        assert_eq!(calclulator.eval("7 2 *").unwrap(), 14);
+    }
+}
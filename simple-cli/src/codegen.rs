@@ -0,0 +1,454 @@
+use crate::calculator::RpnCalculator;
+use crate::error::CalcError;
+use crate::number::Number;
+use crate::parser::{self, Term};
+use crate::value::Value;
+use anyhow::{bail, Result};
+use clap::ArgEnum;
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Lang {
+    Rust,
+    C,
+}
+
+// 変数(引数)の型. intならi64､floatならf64として評価する
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    Int,
+    Float,
+}
+
+// 生成する式の型. Numberと同じく整数同士なら整数､小数が混ざれば浮動小数点数になる
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Ty {
+    Int,
+    Float,
+}
+
+#[derive(Clone, Debug)]
+struct Sym {
+    expr: String,
+    ty: Ty,
+}
+
+// 生成するコードで引数や関数の名前に使えないもの. キーワードと､生成するコードの中で使っている名前
+// ※ 一時変数は _t1, _t2, ... なので､_で始まる名前も引数にはできない
+const RUST_RESERVED: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern", "false", "fn", "for",
+    "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "self", "Self", "static",
+    "struct", "super", "trait", "true", "type", "unsafe", "use", "where", "while", "abstract", "become", "box", "do",
+    "final", "gen", "macro", "override", "priv", "try", "typeof", "unsized", "virtual", "yield", "Some", "None",
+];
+const C_RESERVED: &[&str] = &[
+    "auto", "break", "case", "char", "const", "continue", "default", "do", "double", "else", "enum", "extern", "float",
+    "for", "goto", "if", "inline", "int", "long", "register", "restrict", "return", "short", "signed", "sizeof",
+    "static", "struct", "switch", "typedef", "union", "unsigned", "void", "volatile", "while", "bool", "true", "false",
+    "int64_t", "INT64_MIN", "INT64_MAX", "INFINITY", "fmod", "assert", "main", "out",
+];
+
+// テストで使う引数の値. 0除算とオーバーフローの両方が起きるように選んでいる
+const INT_SAMPLES: [i64; 6] = [3, -2, 0, 7, 1, i64::MAX];
+const FLOAT_SAMPLES: [f64; 5] = [1.5, -2.0, 0.0, 10.0, 0.25];
+
+// 式をスタックごと記号的に評価して､1演算を1文に落とす
+struct Generator {
+    lang: Lang,
+    params: Vec<(String, Ty)>,
+    body: Vec<String>,
+    temps: usize,
+}
+
+impl Generator {
+    fn param(&mut self, name: &str, mode: Mode) -> Sym {
+        let ty = match mode {
+            Mode::Int => Ty::Int,
+            Mode::Float => Ty::Float,
+        };
+        if !self.params.iter().any(|(p, _)| p == name) {
+            self.params.push((name.to_string(), ty));
+        }
+        Sym {
+            expr: name.to_string(),
+            ty,
+        }
+    }
+
+    fn literal(&self, x: Number) -> Sym {
+        match x {
+            Number::Int(x) => Sym {
+                expr: int_literal(self.lang, x),
+                ty: Ty::Int,
+            },
+            Number::Float(x) => Sym {
+                expr: float_literal(self.lang, x),
+                ty: Ty::Float,
+            },
+        }
+    }
+
+    fn temp(&mut self) -> String {
+        self.temps += 1;
+        format!("_t{}", self.temps)
+    }
+
+    fn as_float(&self, x: &Sym) -> String {
+        match (x.ty, self.lang) {
+            (Ty::Float, _) => x.expr.clone(),
+            (Ty::Int, lang) if x.expr.parse::<i64>().is_ok() => float_literal(lang, x.expr.parse::<i64>().unwrap() as f64),
+            (Ty::Int, Lang::Rust) => format!("({} as f64)", x.expr),
+            (Ty::Int, Lang::C) => format!("((double){})", x.expr),
+        }
+    }
+
+    fn arithmetic(&mut self, op: &str, x: Sym, y: Sym) -> Sym {
+        let t = self.temp();
+        if x.ty == Ty::Int && y.ty == Ty::Int {
+            self.int_op(op, &t, &x.expr, &y.expr);
+            return Sym { expr: t, ty: Ty::Int };
+        }
+        let (a, b) = (self.as_float(&x), self.as_float(&y));
+        // インタプリタと同じく､小数でも0除算はエラーにする(0でない定数で割る場合は不要)
+        if (op == "/" || op == "%") && b.parse::<f64>().map_or(true, |b| b == 0.0) {
+            self.body.push(match self.lang {
+                Lang::Rust => format!("if {} == 0.0 {{ return None; }}", b),
+                Lang::C => format!("if ({} == 0.0) return false;", b),
+            });
+        }
+        self.body.push(match (self.lang, op) {
+            (Lang::Rust, _) => format!("let {} = {} {} {};", t, a, op, b),
+            (Lang::C, "%") => format!("double {} = fmod({}, {});", t, a, b),
+            (Lang::C, _) => format!("double {} = {} {} {};", t, a, op, b),
+        });
+        Sym { expr: t, ty: Ty::Float }
+    }
+
+    // 整数はchecked_*(Rust)や__builtin_*_overflow(C)で計算し､オーバーフローしたら失敗を返す
+    fn int_op(&mut self, op: &str, t: &str, a: &str, b: &str) {
+        let line = match self.lang {
+            Lang::Rust => {
+                let method = match op {
+                    "+" => "checked_add",
+                    "-" => "checked_sub",
+                    "*" => "checked_mul",
+                    "/" => "checked_div",
+                    _ => "checked_rem",
+                };
+                // リテラルのままメソッドを呼ぶと型が決まらず(2.checked_mul)､負の数は -(3.checked_add(x)) と
+                // 読まれるので､型を付けて括弧で囲む
+                let a = match a.parse::<i64>() {
+                    Ok(x) if x < 0 => format!("({}i64)", x),
+                    Ok(x) => format!("{}i64", x),
+                    Err(_) => a.to_string(),
+                };
+                format!("let {} = {}.{}({})?;", t, a, method, b)
+            }
+            Lang::C => match op {
+                // 0でも-1でもない定数で割るなら失敗しない
+                "/" | "%" if b.parse::<i64>().is_ok_and(|b| b != 0 && b != -1) => {
+                    format!("int64_t {} = {} {} {};", t, a, op, b)
+                }
+                "/" | "%" => format!(
+                    "if ({b} == 0 || ({a} == INT64_MIN && {b} == -1)) return false;\n    int64_t {t} = {a} {op} {b};",
+                    a = a,
+                    b = b,
+                    t = t,
+                    op = op
+                ),
+                _ => {
+                    let builtin = match op {
+                        "+" => "__builtin_add_overflow",
+                        "-" => "__builtin_sub_overflow",
+                        _ => "__builtin_mul_overflow",
+                    };
+                    format!(
+                        "int64_t {t};\n    if ({}({}, {}, &{t})) return false;",
+                        builtin,
+                        a,
+                        b,
+                        t = t
+                    )
+                }
+            },
+        };
+        self.body.push(line);
+    }
+}
+
+fn int_literal(lang: Lang, x: i64) -> String {
+    match (lang, x) {
+        (Lang::C, i64::MIN) => "INT64_MIN".to_string(),
+        (Lang::C, i64::MAX) => "INT64_MAX".to_string(),
+        (Lang::Rust, i64::MIN) => "i64::MIN".to_string(),
+        (Lang::Rust, i64::MAX) => "i64::MAX".to_string(),
+        _ => x.to_string(),
+    }
+}
+
+// {:?}なら 2.0 のように必ず小数として読める表記になる
+fn float_literal(lang: Lang, x: f64) -> String {
+    match lang {
+        Lang::Rust if x.is_infinite() => format!("{}f64::INFINITY", if x < 0.0 { "-" } else { "" }),
+        Lang::C if x.is_infinite() => format!("{}INFINITY", if x < 0.0 { "-" } else { "" }),
+        _ => format!("{:?}", x),
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn is_reserved(lang: Lang, name: &str) -> bool {
+    match lang {
+        Lang::Rust => RUST_RESERVED.contains(&name),
+        Lang::C => C_RESERVED.contains(&name),
+    }
+}
+
+pub fn generate(formula: &str, name: &str, lang: Lang, mode: Mode) -> Result<String> {
+    if !is_identifier(name) || is_reserved(lang, name) {
+        bail!("`{}` is not a valid function name", name);
+    }
+    let program = parser::parse(formula)?;
+    let mut gen = Generator {
+        lang,
+        params: Vec::new(),
+        body: Vec::new(),
+        temps: 0,
+    };
+    let mut stack: Vec<Sym> = Vec::new();
+    for node in &program {
+        let pos = node.pos;
+        let word = match &node.term {
+            Term::Num(x) => {
+                stack.push(gen.literal(*x));
+                continue;
+            }
+            Term::Word(word) => word.as_str(),
+            Term::List(_) | Term::Quote(_) => bail!("lists and quotes are not supported by codegen (at {})", pos),
+            Term::Quantity(..) | Term::Date(_) | Term::Interval(..) | Term::Str(_) => bail!("`{}` is not supported by codegen (at {})", node, pos),
+        };
+        let mut pop = || stack.pop().ok_or(CalcError::StackUnderflow { pos });
+        match word {
+            "+" | "-" | "*" | "/" | "%" => {
+                let y = pop()?;
+                let x = pop()?;
+                let res = gen.arithmetic(word, x, y);
+                stack.push(res);
+            }
+            "dup" => {
+                let x = pop()?;
+                stack.extend([x.clone(), x]);
+            }
+            "drop" => {
+                pop()?;
+            }
+            "swap" => {
+                let y = pop()?;
+                let x = pop()?;
+                stack.extend([y, x]);
+            }
+            // それ以外の名前は自由変数として引数にする
+            name if is_identifier(name) && RpnCalculator::is_builtin(name) => {
+                bail!("`{}` is not supported by codegen (at {})", name, pos)
+            }
+            name if is_identifier(name) => {
+                if name.starts_with('_') || is_reserved(lang, name) {
+                    bail!("`{}` cannot be used as a parameter name in {:?} (at {})", name, lang, pos)
+                }
+                stack.push(gen.param(name, mode))
+            }
+            _ => bail!("`{}` is not supported by codegen (at {})", word, pos),
+        }
+    }
+    if stack.len() != 1 {
+        return Err(CalcError::InvalidSyntax.into());
+    }
+    let result = stack.remove(0);
+    let tests = test_cases(formula, &gen.params, result.ty, lang)?;
+    Ok(match lang {
+        Lang::Rust => emit_rust(formula, name, &gen, &result, &tests),
+        Lang::C => emit_c(formula, name, &gen, &result, &tests),
+    })
+}
+
+// インタプリタで期待値を計算する. Noneは0除算かオーバーフローで失敗するケース
+fn test_cases(formula: &str, params: &[(String, Ty)], ty: Ty, lang: Lang) -> Result<Vec<(Vec<String>, Option<String>)>> {
+    let calculator = RpnCalculator::new(false);
+    let rows = INT_SAMPLES.len().max(FLOAT_SAMPLES.len());
+    let mut cases = Vec::new();
+    for i in 0..rows {
+        let mut args = Vec::new();
+        for (j, (name, ty)) in params.iter().enumerate() {
+            let value = match ty {
+                Ty::Int => Number::Int(INT_SAMPLES[(i + j) % INT_SAMPLES.len()]),
+                Ty::Float => Number::Float(FLOAT_SAMPLES[(i + j) % FLOAT_SAMPLES.len()]),
+            };
+            calculator.set_var(name, Value::Num(value));
+            args.push(match value {
+                Number::Int(x) => int_literal(lang, x),
+                Number::Float(x) => float_literal(lang, x),
+            });
+        }
+        let expected = match calculator.eval(formula) {
+            Ok(Value::Num(Number::Int(x))) if ty == Ty::Int => Some(int_literal(lang, x)),
+            Ok(Value::Num(Number::Float(x))) if ty == Ty::Float => {
+                // NaNは等値比較できないのでテストから外す
+                if x.is_nan() {
+                    continue;
+                }
+                Some(float_literal(lang, x))
+            }
+            Ok(other) => bail!("interpreter returned unexpected value {}", other),
+            Err(CalcError::DivisionByZero { .. }) | Err(CalcError::Overflow { .. }) => None,
+            Err(e) => return Err(e.into()),
+        };
+        if !cases.iter().any(|(a, _)| *a == args) {
+            cases.push((args, expected));
+        }
+    }
+    Ok(cases)
+}
+
+fn emit_rust(formula: &str, name: &str, gen: &Generator, result: &Sym, tests: &[(Vec<String>, Option<String>)]) -> String {
+    let ty = |ty: &Ty| match ty {
+        Ty::Int => "i64",
+        Ty::Float => "f64",
+    };
+    let params = gen
+        .params
+        .iter()
+        .map(|(p, t)| format!("{}: {}", p, ty(t)))
+        .collect::<Vec<_>>();
+    let mut out = format!("// Generated by rpncalc codegen from: {}\n", formula);
+    out += "// Returns None on division by zero or integer overflow.\n";
+    out += &format!("pub fn {}({}) -> Option<{}> {{\n", name, params.join(", "), ty(&result.ty));
+    for line in &gen.body {
+        out += &format!("    {}\n", line);
+    }
+    out += &format!("    Some({})\n}}\n\n", result.expr);
+    out += &format!("#[cfg(test)]\nmod {}_tests {{\n    use super::*;\n\n", name);
+    out += "    // expected values were computed by the rpncalc interpreter\n";
+    out += &format!("    #[test]\n    fn {}_matches_interpreter() {{\n", name);
+    for (args, expected) in tests {
+        let expected = match expected {
+            Some(x) => format!("Some({})", x),
+            None => "None".to_string(),
+        };
+        out += &format!("        assert_eq!({}({}), {});\n", name, args.join(", "), expected);
+    }
+    out += "    }\n}\n";
+    out
+}
+
+fn emit_c(formula: &str, name: &str, gen: &Generator, result: &Sym, tests: &[(Vec<String>, Option<String>)]) -> String {
+    let ty = |ty: &Ty| match ty {
+        Ty::Int => "int64_t",
+        Ty::Float => "double",
+    };
+    let mut params = gen
+        .params
+        .iter()
+        .map(|(p, t)| format!("{} {}", ty(t), p))
+        .collect::<Vec<_>>();
+    params.push(format!("{} *out", ty(&result.ty)));
+    let mut out = format!("/* Generated by rpncalc codegen from: {} */\n", formula);
+    out += "/* Returns false on division by zero or integer overflow. */\n";
+    out += "#include <math.h>\n#include <stdbool.h>\n#include <stdint.h>\n\n";
+    out += &format!("bool {}({}) {{\n", name, params.join(", "));
+    for line in &gen.body {
+        out += &format!("    {}\n", line);
+    }
+    out += &format!("    *out = {};\n    return true;\n}}\n\n", result.expr);
+    out += "/* expected values were computed by the rpncalc interpreter */\n";
+    out += "#ifdef RPNCALC_TEST\n#include <assert.h>\n\nint main(void) {\n";
+    out += &format!("    {} out;\n", ty(&result.ty));
+    for (args, expected) in tests {
+        let mut args = args.clone();
+        args.push("&out".to_string());
+        out += &match expected {
+            Some(x) => format!("    assert({}({}) && out == {});\n", name, args.join(", "), x),
+            None => format!("    assert(!{}({}));\n", name, args.join(", ")),
+        };
+    }
+    out += "    return 0;\n}\n#endif\n";
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rust() {
+        let code = generate("qty price * 100 /", "price_fn", Lang::Rust, Mode::Int).unwrap();
+        assert!(code.contains("pub fn price_fn(qty: i64, price: i64) -> Option<i64> {"), "{}", code);
+        assert!(code.contains("let _t1 = qty.checked_mul(price)?;"), "{}", code);
+        assert!(code.contains("let _t2 = _t1.checked_div(100)?;"), "{}", code);
+        assert!(code.contains("assert_eq!(price_fn(3, -2), Some(0));"), "{}", code);
+        assert!(code.contains("assert_eq!(price_fn(i64::MAX, 3), None);"), "{}", code);
+    }
+
+    #[test]
+    fn test_c() {
+        let code = generate("x 2 / 0.5 +", "half", Lang::C, Mode::Float).unwrap();
+        assert!(code.contains("bool half(double x, double *out) {"), "{}", code);
+        assert!(code.contains("double _t1 = x / 2.0;"), "{}", code);
+        assert!(code.contains("assert(half(1.5, &out) && out == 1.25);"), "{}", code);
+        let code = generate("a b /", "ratio", Lang::C, Mode::Int).unwrap();
+        assert!(code.contains("if (b == 0 || (a == INT64_MIN && b == -1)) return false;"), "{}", code);
+    }
+
+    // 左の項がリテラルでもコンパイルでき､インタプリタと同じ値になること
+    #[test]
+    fn test_rust_literal() {
+        let code = generate("2 x * -3 x + + x 0.5 * 2 3 * + +", "lit", Lang::Rust, Mode::Int).unwrap();
+        assert!(code.contains("let _t1 = 2i64.checked_mul(x)?;"), "{}", code);
+        assert!(code.contains("let _t2 = (-3i64).checked_add(x)?;"), "{}", code);
+        assert!(code.contains("let _t5 = 2i64.checked_mul(3)?;"), "{}", code);
+
+        let dir = std::env::temp_dir().join(format!("rpncalc-codegen-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("lit.rs");
+        std::fs::write(&source, &code).unwrap();
+        let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+        let status = std::process::Command::new(rustc)
+            .args(["--edition", "2021", "--test", "-o"])
+            .arg(dir.join("lit"))
+            .arg(&source)
+            .status()
+            .unwrap();
+        assert!(status.success(), "{}", code);
+        let output = std::process::Command::new(dir.join("lit")).output().unwrap();
+        assert!(output.status.success(), "{}\n{}", code, String::from_utf8_lossy(&output.stdout));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_unsupported() {
+        assert!(generate("x 1 <", "f", Lang::Rust, Mode::Int).is_err());
+        assert!(generate("[1 2] sum", "f", Lang::Rust, Mode::Int).is_err());
+        assert!(generate("x y", "f", Lang::Rust, Mode::Int).is_err());
+        assert!(generate("x", "1f", Lang::Rust, Mode::Int).is_err());
+    }
+
+    // 引数の名前が一時変数やキーワードと重ならないこと
+    #[test]
+    fn test_names() {
+        let code = generate("t2 2 * 3 + t2 +", "f", Lang::Rust, Mode::Int).unwrap();
+        assert!(code.contains("let _t2 = _t1.checked_add(3)?;"), "{}", code);
+        assert!(code.contains("let _t3 = _t2.checked_add(t2)?;"), "{}", code);
+        assert!(code.contains("assert_eq!(f(3), Some(12));"), "{}", code);
+        let code = generate("t1 2 *", "f", Lang::C, Mode::Int).unwrap();
+        assert!(code.contains("bool f(int64_t t1, int64_t *out) {"), "{}", code);
+        assert!(code.contains("int64_t _t1;\n    if (__builtin_mul_overflow(t1, 2, &_t1)) return false;"), "{}", code);
+
+        assert!(generate("type 1 +", "f", Lang::Rust, Mode::Int).is_err());
+        assert!(generate("type 1 +", "f", Lang::C, Mode::Int).is_ok());
+        assert!(generate("int 1 +", "f", Lang::C, Mode::Int).is_err());
+        assert!(generate("out 1 +", "f", Lang::C, Mode::Int).is_err());
+        assert!(generate("_t1 1 +", "f", Lang::Rust, Mode::Int).is_err());
+        assert!(generate("x 1 +", "fn", Lang::Rust, Mode::Int).is_err());
+    }
+}
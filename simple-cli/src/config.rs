@@ -0,0 +1,127 @@
+use crate::calculator::{NumberMode, StackPolicy};
+use crate::format::RADIXES;
+use crate::locale::{Lang, Numbers};
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// ~/.config/rpncalc/config.toml の中身. コマンドラインで指定した値が優先される
+//
+//   mode = "float"          # auto | int | float | interval
+//   radix = 16              # 2 | 8 | 10 | 16
+//   precision = 4
+//   stack-policy = "top"    # strict | top | all
+//   verbose = false
+//   prelude = "prelude.rpn" # 相対パスは設定ファイルのディレクトリから探す
+//   lang = "ja"             # en | ja  省略時は環境変数LANGで決める
+//   numbers = "de"          # c | en | de
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pub mode: Option<NumberMode>,
+    pub radix: Option<u32>,
+    pub precision: Option<usize>,
+    pub stack_policy: Option<StackPolicy>,
+    pub verbose: Option<bool>,
+    pub prelude: Option<PathBuf>,
+    pub lang: Option<Lang>,
+    pub numbers: Option<Numbers>,
+}
+
+impl Config {
+    // --configで指定されたファイルは必須､既定の場所のファイルはなければ空の設定にする
+    pub fn load(path: Option<&Path>) -> Result<Config> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Config::default()),
+            },
+        };
+        let text = fs::read_to_string(&path).with_context(|| format!("failed to read {}", path.display()))?;
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+        Config::parse(&text, base).with_context(|| format!("invalid config {}", path.display()))
+    }
+
+    pub fn parse(text: &str, base: &Path) -> Result<Config> {
+        let mut config: Config = toml::from_str(text)?;
+        if let Some(radix) = config.radix.filter(|radix| !RADIXES.contains(radix)) {
+            bail!("radix must be one of 2, 8, 10 or 16 but got {}", radix);
+        }
+        config.prelude = config.prelude.map(|prelude| base.join(prelude));
+        Ok(config)
+    }
+
+    // コマンドラインの設定(other)で上書きする
+    pub fn merge(self, other: Config) -> Config {
+        Config {
+            mode: other.mode.or(self.mode),
+            radix: other.radix.or(self.radix),
+            precision: other.precision.or(self.precision),
+            stack_policy: other.stack_policy.or(self.stack_policy),
+            verbose: other.verbose.or(self.verbose),
+            prelude: other.prelude.or(self.prelude),
+            lang: other.lang.or(self.lang),
+            numbers: other.numbers.or(self.numbers),
+        }
+    }
+}
+
+// XDG_CONFIG_HOMEがなければ ~/.config を使う
+fn default_path() -> Option<PathBuf> {
+    let dir = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(dir.join("rpncalc").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let text = "mode = \"float\"\nradix = 16\nstack-policy = \"all\"\nprelude = \"defs.rpn\"\nnumbers = \"de\"\n";
+        let config = Config::parse(text, Path::new("/etc/rpncalc")).unwrap();
+        assert_eq!(
+            config,
+            Config {
+                mode: Some(NumberMode::Float),
+                radix: Some(16),
+                stack_policy: Some(StackPolicy::All),
+                prelude: Some(PathBuf::from("/etc/rpncalc/defs.rpn")),
+                numbers: Some(Numbers::De),
+                ..Config::default()
+            }
+        );
+        assert!(Config::parse("radix = 3", Path::new("")).is_err());
+        assert!(Config::parse("mode = \"fast\"", Path::new("")).is_err());
+        assert!(Config::parse("colour = true", Path::new("")).is_err());
+    }
+
+    #[test]
+    fn test_merge() {
+        let file = Config {
+            radix: Some(16),
+            precision: Some(2),
+            ..Config::default()
+        };
+        let cli = Config {
+            radix: Some(10),
+            ..Config::default()
+        };
+        let merged = file.merge(cli);
+        assert_eq!(merged.radix, Some(10));
+        assert_eq!(merged.precision, Some(2));
+    }
+
+    #[test]
+    fn test_load() {
+        assert!(Config::load(Some(Path::new("assets/config/missing.toml"))).is_err());
+        let config = Config::load(Some(Path::new("assets/config/config.toml"))).unwrap();
+        assert_eq!(config.prelude, Some(PathBuf::from("assets/config/prelude.rpn")));
+    }
+}
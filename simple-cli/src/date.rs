@@ -0,0 +1,199 @@
+use std::fmt;
+
+// 日付. 1970-01-01からの日数で持つ(グレゴリオ暦を過去にも延長して数える)
+//
+// スタック効果(-- の左が取り出す値､右が積む値)
+//   +              ( date duration -- date )  durationは 3d 2w などの時間. 1日単位でなければエラー
+//   -              ( date date -- duration )  日数(d)で返す
+//   weekday        ( date -- n )              ISO 8601の曜日. 月曜が1､日曜が7
+//   business-days  ( date n -- date )         土日を除いてn日進める(負なら戻る)
+//   business-days  ( date date -- n )         2つ目の日付までの営業日数(1つ目の日付は含まない)
+//   eom            ( date -- date )           月末日
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Date {
+    days: i64,
+}
+
+// 1970-01-05 は月曜日
+const MONDAY: i64 = 4;
+// 表示した日付をそのまま読み直せるよう､年が4桁に収まる範囲(0000-01-01〜9999-12-31)に制限する
+const MIN_DAYS: i64 = days_from_civil(0, 1, 1);
+const MAX_DAYS: i64 = days_from_civil(9999, 12, 31);
+
+impl Date {
+    pub fn from_ymd(year: i64, month: u32, day: u32) -> Option<Date> {
+        if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+            return None;
+        }
+        Some(Date {
+            days: days_from_civil(year, month, day),
+        })
+    }
+
+    // "2026-10-18" の形だけを受け付ける
+    pub fn parse(text: &str) -> Option<Date> {
+        let mut parts = text.splitn(3, '-');
+        let mut field = |len: usize| {
+            let part = parts.next().filter(|p| p.len() == len && p.bytes().all(|b| b.is_ascii_digit()))?;
+            part.parse::<u32>().ok()
+        };
+        let (year, month, day) = (field(4)?, field(2)?, field(2)?);
+        Date::from_ymd(year as i64, month, day)
+    }
+
+    pub fn ymd(self) -> (i64, u32, u32) {
+        civil_from_days(self.days)
+    }
+
+    pub fn add_days(self, n: i64) -> Option<Date> {
+        self.days
+            .checked_add(n)
+            .filter(|days| (MIN_DAYS..=MAX_DAYS).contains(days))
+            .map(|days| Date { days })
+    }
+
+    pub fn days_until(self, other: Date) -> i64 {
+        other.days - self.days
+    }
+
+    pub fn weekday(self) -> u32 {
+        (self.days - MONDAY).rem_euclid(7) as u32 + 1
+    }
+
+    pub fn is_weekend(self) -> bool {
+        self.weekday() >= 6
+    }
+
+    pub fn end_of_month(self) -> Date {
+        let (year, month, _) = self.ymd();
+        Date {
+            days: days_from_civil(year, month, days_in_month(year, month)),
+        }
+    }
+
+    // 平日から5営業日進めるとちょうど1週間後になるので､まとめて進めてから残りを1日ずつ進める
+    pub fn add_business_days(self, n: i64) -> Option<Date> {
+        let step = n.signum();
+        let mut left = n.checked_abs()?;
+        let mut date = self;
+        while left > 0 {
+            if !date.is_weekend() && left >= 5 {
+                date = date.add_days((left / 5).checked_mul(7 * step)?)?;
+                left %= 5;
+                continue;
+            }
+            date = date.add_days(step)?;
+            if !date.is_weekend() {
+                left -= 1;
+            }
+        }
+        Some(date)
+    }
+
+    // selfの翌日からotherまで(otherを含む)の営業日数. otherが前なら負の数
+    pub fn business_days_until(self, other: Date) -> i64 {
+        weekdays_through(other.days) - weekdays_through(self.days)
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (year, month, day) = self.ymd();
+        write!(f, "{:04}-{:02}-{:02}", year, month, day)
+    }
+}
+
+// 基準の月曜日からdaysまでの平日の数(差を取るためだけに使う)
+fn weekdays_through(days: i64) -> i64 {
+    let t = days - MONDAY;
+    t.div_euclid(7) * 5 + (t.rem_euclid(7) + 1).min(5)
+}
+
+fn is_leap_year(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// 暦日と通日の変換. 3月始まりの年で数えると閏日が年の最後に来るので計算が簡単になる
+// (http://howardhinnant.github.io/date_algorithms.html)
+const fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(text: &str) -> Date {
+        Date::parse(text).unwrap()
+    }
+
+    #[test]
+    fn test_civil() {
+        assert_eq!(date("1970-01-01").days, 0);
+        assert_eq!(date("2000-03-01").days, 11_017);
+        assert_eq!(date("2024-02-29").to_string(), "2024-02-29");
+        assert_eq!(Date::parse("2023-02-29"), None);
+        assert_eq!(Date::parse("2026-13-01"), None);
+        assert_eq!(Date::parse("2026-1-01"), None);
+        assert_eq!(date("1900-02-28").add_days(1).unwrap().to_string(), "1900-03-01");
+        assert_eq!(date("2026-10-18").weekday(), 7);
+        assert_eq!(date("2024-02-10").end_of_month().to_string(), "2024-02-29");
+    }
+
+    // 範囲の端の日付も表示したものを読み直せる
+    #[test]
+    fn test_range() {
+        let last = date("9999-12-31");
+        assert_eq!(last.add_days(1), None);
+        assert_eq!(date("9999-12-30").add_days(1), Some(last));
+        assert_eq!(date("0000-01-01").add_days(-1), None);
+        assert_eq!(date("0000-01-01").to_string(), "0000-01-01");
+        assert_eq!(last.add_business_days(1), None);
+        assert_eq!(Date::parse(&last.to_string()), Some(last));
+    }
+
+    #[test]
+    fn test_business_days() {
+        // 2026-10-16 は金曜日
+        let friday = date("2026-10-16");
+        assert_eq!(friday.add_business_days(1).unwrap().to_string(), "2026-10-19");
+        assert_eq!(friday.add_business_days(5).unwrap().to_string(), "2026-10-23");
+        assert_eq!(friday.add_business_days(-5).unwrap().to_string(), "2026-10-09");
+        assert_eq!(date("2026-10-17").add_business_days(1).unwrap().to_string(), "2026-10-19");
+        assert_eq!(date("2026-10-18").add_business_days(45).unwrap().to_string(), "2026-12-18");
+        for n in -30..30 {
+            let end = friday.add_business_days(n).unwrap();
+            assert_eq!(friday.business_days_until(end), n, "{}", n);
+        }
+        assert_eq!(date("2026-10-17").business_days_until(date("2026-10-18")), 0);
+        assert_eq!(friday.add_business_days(i64::MAX), None);
+    }
+}
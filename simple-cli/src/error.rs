@@ -0,0 +1,198 @@
+use crate::locale::Lang;
+use std::time::Duration;
+use thiserror::Error;
+
+// 計算機のエラー. posはトークンの位置(1始まり)
+#[derive(Error, Debug, PartialEq)]
+pub enum CalcError {
+    #[error("stack underflow at {pos}")]
+    StackUnderflow { pos: usize },
+    #[error("invalid token `{token}` at {pos}")]
+    InvalidToken { pos: usize, token: String },
+    #[error("division by zero at {pos}")]
+    DivisionByZero { pos: usize },
+    #[error("overflow at {pos}")]
+    Overflow { pos: usize },
+    #[error("invalid argument at {pos}: {reason}")]
+    InvalidArgument { pos: usize, reason: String },
+    #[error("type mismatch at {pos}: expected {expected} but got {actual}")]
+    TypeMismatch {
+        pos: usize,
+        expected: &'static str,
+        actual: &'static str,
+    },
+    #[error("unterminated string at {pos}")]
+    UnterminatedString { pos: usize },
+    #[error("unterminated comment at {pos}")]
+    UnterminatedComment { pos: usize },
+    #[error("unbalanced bracket at {pos}")]
+    UnbalancedBracket { pos: usize },
+    #[error("shape mismatch at {pos}: {reason}")]
+    ShapeMismatch { pos: usize, reason: String },
+    #[error("singular matrix at {pos}")]
+    SingularMatrix { pos: usize },
+    #[error("dimension mismatch at {pos}: {left} is not compatible with {right}")]
+    DimensionMismatch {
+        pos: usize,
+        left: String,
+        right: String,
+    },
+    // 以下はLimitsで決めた上限を超えた場合
+    #[error("step limit of {limit} exceeded at {pos}")]
+    StepLimitExceeded { pos: usize, limit: usize },
+    #[error("stack limit of {limit} values exceeded at {pos}")]
+    StackLimitExceeded { pos: usize, limit: usize },
+    #[error("nesting depth limit of {limit} exceeded at {pos}")]
+    DepthLimitExceeded { pos: usize, limit: usize },
+    #[error("token limit of {limit} exceeded at {pos}")]
+    TokenLimitExceeded { pos: usize, limit: usize },
+    #[error("digit limit of {limit} exceeded at {pos}")]
+    DigitLimitExceeded { pos: usize, limit: usize },
+    #[error("memory limit of {limit} bytes exceeded at {pos}")]
+    MemoryLimitExceeded { pos: usize, limit: usize },
+    #[error("time limit of {limit:?} exceeded at {pos}")]
+    TimeLimitExceeded { pos: usize, limit: Duration },
+    // 評価後のスタックに値がちょうど1つ残らなかった
+    #[error("invalid syntax")]
+    InvalidSyntax,
+}
+
+impl CalcError {
+    // エラーの種類名. golden testの "# expect-error: 種類名" と比較する
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CalcError::StackUnderflow { .. } => "StackUnderflow",
+            CalcError::InvalidToken { .. } => "InvalidToken",
+            CalcError::DivisionByZero { .. } => "DivisionByZero",
+            CalcError::Overflow { .. } => "Overflow",
+            CalcError::InvalidArgument { .. } => "InvalidArgument",
+            CalcError::TypeMismatch { .. } => "TypeMismatch",
+            CalcError::UnterminatedString { .. } => "UnterminatedString",
+            CalcError::UnterminatedComment { .. } => "UnterminatedComment",
+            CalcError::UnbalancedBracket { .. } => "UnbalancedBracket",
+            CalcError::DimensionMismatch { .. } => "DimensionMismatch",
+            CalcError::ShapeMismatch { .. } => "ShapeMismatch",
+            CalcError::SingularMatrix { .. } => "SingularMatrix",
+            CalcError::StepLimitExceeded { .. } => "StepLimitExceeded",
+            CalcError::StackLimitExceeded { .. } => "StackLimitExceeded",
+            CalcError::DepthLimitExceeded { .. } => "DepthLimitExceeded",
+            CalcError::TokenLimitExceeded { .. } => "TokenLimitExceeded",
+            CalcError::DigitLimitExceeded { .. } => "DigitLimitExceeded",
+            CalcError::MemoryLimitExceeded { .. } => "MemoryLimitExceeded",
+            CalcError::TimeLimitExceeded { .. } => "TimeLimitExceeded",
+            CalcError::InvalidSyntax => "InvalidSyntax",
+        }
+    }
+
+    // エラーが起きたトークンの位置. 式全体に対するエラーならNone
+    pub fn pos(&self) -> Option<usize> {
+        match self {
+            CalcError::StackUnderflow { pos }
+            | CalcError::InvalidToken { pos, .. }
+            | CalcError::DivisionByZero { pos }
+            | CalcError::Overflow { pos }
+            | CalcError::InvalidArgument { pos, .. }
+            | CalcError::TypeMismatch { pos, .. }
+            | CalcError::UnterminatedString { pos }
+            | CalcError::UnterminatedComment { pos }
+            | CalcError::UnbalancedBracket { pos }
+            | CalcError::ShapeMismatch { pos, .. }
+            | CalcError::SingularMatrix { pos }
+            | CalcError::DimensionMismatch { pos, .. }
+            | CalcError::StepLimitExceeded { pos, .. }
+            | CalcError::StackLimitExceeded { pos, .. }
+            | CalcError::DepthLimitExceeded { pos, .. }
+            | CalcError::TokenLimitExceeded { pos, .. }
+            | CalcError::DigitLimitExceeded { pos, .. }
+            | CalcError::MemoryLimitExceeded { pos, .. }
+            | CalcError::TimeLimitExceeded { pos, .. } => Some(*pos),
+            CalcError::InvalidSyntax => None,
+        }
+    }
+
+    // 利用者に見せるメッセージ. 英語はDisplayと同じ
+    // ※ InvalidArgumentなどのreasonは計算機の中で作った英語のまま埋め込む
+    pub fn message(&self, lang: Lang) -> String {
+        if lang == Lang::En {
+            return self.to_string();
+        }
+        match self {
+            CalcError::StackUnderflow { pos } => format!("{}番目でスタックが足りません", pos),
+            CalcError::InvalidToken { pos, token } => format!("{}番目のトークン`{}`が不正です", pos, token),
+            CalcError::DivisionByZero { pos } => format!("{}番目で0で割りました", pos),
+            CalcError::Overflow { pos } => format!("{}番目でオーバーフローしました", pos),
+            CalcError::InvalidArgument { pos, reason } => format!("{}番目の引数が不正です: {}", pos, reason),
+            CalcError::TypeMismatch { pos, expected, actual } => format!(
+                "{}番目の型が違います: {}ではなく{}でした",
+                pos,
+                type_name_ja(expected),
+                type_name_ja(actual)
+            ),
+            CalcError::UnterminatedString { pos } => format!("{}番目の文字列が閉じられていません", pos),
+            CalcError::UnterminatedComment { pos } => format!("{}番目のコメントが閉じられていません", pos),
+            CalcError::UnbalancedBracket { pos } => format!("{}番目の括弧が対応していません", pos),
+            CalcError::ShapeMismatch { pos, reason } => format!("{}番目で行列の形が合いません: {}", pos, reason),
+            CalcError::SingularMatrix { pos } => format!("{}番目の行列は正則ではありません", pos),
+            CalcError::DimensionMismatch { pos, left, right } => {
+                format!("{}番目で次元が合いません: {}と{}", pos, left, right)
+            }
+            CalcError::StepLimitExceeded { pos, limit } => {
+                format!("{}番目でステップ数の上限{}を超えました", pos, limit)
+            }
+            CalcError::StackLimitExceeded { pos, limit } => {
+                format!("{}番目でスタックに積める値の数の上限{}を超えました", pos, limit)
+            }
+            CalcError::DepthLimitExceeded { pos, limit } => {
+                format!("{}番目で入れ子の深さの上限{}を超えました", pos, limit)
+            }
+            CalcError::TokenLimitExceeded { pos, limit } => {
+                format!("{}番目でトークン数の上限{}を超えました", pos, limit)
+            }
+            CalcError::DigitLimitExceeded { pos, limit } => format!("{}番目で桁数の上限{}を超えました", pos, limit),
+            CalcError::MemoryLimitExceeded { pos, limit } => {
+                format!("{}番目でメモリの上限{}バイトを超えました", pos, limit)
+            }
+            CalcError::TimeLimitExceeded { pos, limit } => {
+                format!("{}番目で時間の上限{:?}を超えました", pos, limit)
+            }
+            CalcError::InvalidSyntax => "式の結果が1つの値になりません".to_string(),
+        }
+    }
+}
+
+// Value::type_name や TypeMismatch の expected に使う名前. "number or date" のような組み合わせも訳す
+fn type_name_ja(name: &str) -> String {
+    name.split(" or ")
+        .map(|name| match name {
+            "number" => "数値",
+            "list" => "リスト",
+            "quote" => "クォーテーション",
+            "quantity" => "単位付きの値",
+            "unit" => "単位",
+            "date" => "日付",
+            "interval" => "区間",
+            "string" => "文字列",
+            "duration" => "期間",
+            other => other,
+        })
+        .collect::<Vec<_>>()
+        .join("か")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message() {
+        let e = CalcError::DivisionByZero { pos: 3 };
+        assert_eq!(e.message(Lang::En), "division by zero at 3");
+        assert_eq!(e.message(Lang::Ja), "3番目で0で割りました");
+        let e = CalcError::TypeMismatch {
+            pos: 2,
+            expected: "string or list",
+            actual: "number",
+        };
+        assert_eq!(e.message(Lang::Ja), "2番目の型が違います: 文字列かリストではなく数値でした");
+    }
+}
@@ -0,0 +1,152 @@
+use crate::json::json_string;
+use crate::report::{Kind, Report};
+use std::env;
+use std::process::{ExitCode, Termination};
+
+// mainの戻り値にして､エラーの種類ごとに違う終了コードで終わる
+//
+//   fn main() -> Exit {
+//       Exit::new(run().map(|x| println!("{}", x)), Style::from_args())
+//   }
+//
+// 終了コード(sysexits.h)
+//   66 EX_NOINPUT  ファイルが無い
+//   77 EX_NOPERM   権限が無い
+//   74 EX_IOERR    その他の入出力のエラー
+//   65 EX_DATAERR  数値として読めない
+//   79             検査のエラー(bail!やensure!など)
+//   70 EX_SOFTWARE パニック(panic_boundary.rs)
+// ※ 検査のエラーに当てはまるコードがsysexits.hに無い(EX_DATAERRは解析のエラーに使った)ので､EX__MAX(78)の次を使う
+pub const EX_DATAERR: u8 = 65;
+pub const EX_NOINPUT: u8 = 66;
+pub const EX_SOFTWARE: u8 = 70;
+pub const EX_IOERR: u8 = 74;
+pub const EX_NOPERM: u8 = 77;
+pub const EX_VALIDATION: u8 = 79;
+
+pub fn code(kind: Kind) -> u8 {
+    match kind {
+        Kind::NotFound => EX_NOINPUT,
+        Kind::PermissionDenied => EX_NOPERM,
+        Kind::Io => EX_IOERR,
+        Kind::Parse => EX_DATAERR,
+        Kind::Validation => EX_VALIDATION,
+        Kind::Panic => EX_SOFTWARE,
+    }
+}
+
+// 標準エラーに出す形
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Style {
+    // "Error: failed to parse string: invalid digit found in string"
+    Line,
+    // {"kind":"parse","code":65,"message":"failed to parse string","causes":["invalid digit found in string"]}
+    Json,
+    // 原因を番号付きで並べ､バックトレースも付ける(report.rs)
+    Full,
+}
+
+impl Style {
+    // clapを使わないサンプル用. --json か --verbose があればそれに従う
+    pub fn from_args() -> Style {
+        let args = env::args().skip(1).collect::<Vec<_>>();
+        if args.iter().any(|a| a == "--json") {
+            Style::Json
+        } else if args.iter().any(|a| a == "--verbose") {
+            Style::Full
+        } else {
+            Style::Line
+        }
+    }
+}
+
+pub struct Exit {
+    result: Result<(), Report>,
+    style: Style,
+}
+
+impl Exit {
+    pub fn new(result: Result<(), Report>, style: Style) -> Exit {
+        Exit { result, style }
+    }
+
+    // 標準エラーに出す内容. 成功ならNone
+    pub fn message(&self) -> Option<String> {
+        let report = self.result.as_ref().err()?;
+        Some(match self.style {
+            Style::Line => format!("Error: {}", report.line()),
+            Style::Json => json(report),
+            Style::Full => format!("Error: {}", report),
+        })
+    }
+}
+
+impl Termination for Exit {
+    fn report(self) -> ExitCode {
+        match self.message() {
+            Some(message) => {
+                eprintln!("{}", message);
+                ExitCode::from(code(self.result.unwrap_err().kind()))
+            }
+            None => ExitCode::SUCCESS,
+        }
+    }
+}
+
+fn json(report: &Report) -> String {
+    let causes = report.causes().iter().map(|c| json_string(c)).collect::<Vec<_>>();
+    format!(
+        r#"{{"kind":{},"code":{},"message":{},"causes":[{}]}}"#,
+        json_string(report.kind().name()),
+        code(report.kind()),
+        json_string(report.message()),
+        causes.join(",")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::number_source::{manual, with_anyhow, with_thiserror};
+    use std::path::Path;
+
+    fn exit(case: &str, style: Style) -> [Exit; 3] {
+        let path = Path::new("assets/number").join(format!("{}.txt", case));
+        [
+            manual::from_path(&path).map(drop).map_err(|e| Report::new(&e)),
+            with_thiserror::from_path(&path).map(drop).map_err(|e| Report::new(&e)),
+            with_anyhow::from_path(&path).map(drop).map_err(Report::from),
+        ]
+        .map(|result| Exit::new(result, style))
+    }
+
+    #[test]
+    fn test_codes() {
+        let codes = |case: &str| {
+            exit(case, Style::Line).map(|e| e.result.as_ref().err().map_or(0, |r| code(r.kind())))
+        };
+        assert_eq!(codes("ok"), [0; 3]);
+        assert_eq!(codes("missing"), [EX_NOINPUT; 3]);
+        assert_eq!(codes("alpha"), [EX_DATAERR; 3]);
+        assert_eq!(codes("two"), [EX_VALIDATION; 3]);
+    }
+
+    #[test]
+    fn test_message() {
+        let [manual, thiserror, anyhow] = exit("alpha", Style::Line);
+        assert_eq!(manual.message().unwrap(), "Error: Parse Error: invalid digit found in string");
+        assert_eq!(thiserror.message().unwrap(), "Error: failed to parse string: invalid digit found in string");
+        assert_eq!(thiserror.message(), anyhow.message());
+        assert_eq!(exit("ok", Style::Json)[0].message(), None);
+        let [_, thiserror, _] = exit("long", Style::Json);
+        assert_eq!(
+            thiserror.message().unwrap(),
+            r#"{"kind":"validation","code":79,"message":"it may be too large number (12 digits)","causes":[]}"#
+        );
+        let [manual, ..] = exit("missing", Style::Json);
+        assert_eq!(
+            manual.message().unwrap(),
+            r#"{"kind":"not-found","code":66,"message":"I/O Error","causes":["No such file or directory (os error 2)"]}"#
+        );
+    }
+}
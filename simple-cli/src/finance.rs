@@ -0,0 +1,183 @@
+// 貨幣の時間価値の計算. 引数の順番と符号の向きは表計算ソフトのFV/PV/PMT/NPER/RATE/NPV/IRRに合わせている
+// (支払いは期末. 受け取る額は正､支払う額は負で表す)
+//
+// スタック効果(-- の左が取り出す値､右が積む値)
+//   fv         ( rate nper pmt pv -- fv )        将来価値
+//   pv         ( rate nper pmt fv -- pv )        現在価値
+//   pmt        ( rate nper pv fv -- pmt )        毎期の支払額
+//   nper       ( rate pmt pv fv -- nper )        期間数
+//   rate       ( nper pmt pv fv -- rate )        1期あたりの利率
+//   npv        ( rate [cf1 cf2 ...] -- npv )     正味現在価値(最初のキャッシュフローは1期後)
+//   irr        ( [cf0 cf1 ...] -- rate )         内部収益率(最初のキャッシュフローは現時点)
+//   compound   ( principal rate n years -- amount )  年n回複利
+//   continuous ( principal rate years -- amount )    連続複利
+
+type Result<T> = std::result::Result<T, String>;
+
+// rateとirrのニュートン法の設定. 許容誤差は金額の大きさに対する比
+const MAX_ITERATIONS: usize = 100;
+const TOLERANCE: f64 = 1e-14;
+
+pub fn fv(rate: f64, nper: f64, pmt: f64, pv: f64) -> f64 {
+    if rate == 0.0 {
+        return -(pv + pmt * nper);
+    }
+    let growth = (1.0 + rate).powf(nper);
+    -(pv * growth + pmt * (growth - 1.0) / rate)
+}
+
+pub fn pv(rate: f64, nper: f64, pmt: f64, fv: f64) -> f64 {
+    if rate == 0.0 {
+        return -(fv + pmt * nper);
+    }
+    let growth = (1.0 + rate).powf(nper);
+    -(fv + pmt * (growth - 1.0) / rate) / growth
+}
+
+pub fn pmt(rate: f64, nper: f64, pv: f64, fv: f64) -> Result<f64> {
+    if nper == 0.0 {
+        return Err("nper must not be zero".to_string());
+    }
+    if rate == 0.0 {
+        return Ok(-(pv + fv) / nper);
+    }
+    let growth = (1.0 + rate).powf(nper);
+    Ok(-(pv * growth + fv) * rate / (growth - 1.0))
+}
+
+pub fn nper(rate: f64, pmt: f64, pv: f64, fv: f64) -> Result<f64> {
+    if rate == 0.0 {
+        if pmt == 0.0 {
+            return Err("pmt must not be zero when rate is zero".to_string());
+        }
+        return Ok(-(pv + fv) / pmt);
+    }
+    let ratio = (pmt - fv * rate) / (pmt + pv * rate);
+    if ratio <= 0.0 || !ratio.is_finite() {
+        return Err("the loan is never paid off with this payment".to_string());
+    }
+    Ok(ratio.ln() / (1.0 + rate).ln())
+}
+
+// 年金現価の係数 ((1+r)^n - 1) / r. rが0に近いと引き算で桁が落ちるので級数で計算する
+//   n + n(n-1)/2 r + n(n-1)(n-2)/6 r^2 + ...
+fn annuity_factor(r: f64, n: f64) -> f64 {
+    if r.abs() < 1e-6 {
+        n + n * (n - 1.0) / 2.0 * r + n * (n - 1.0) * (n - 2.0) / 6.0 * r * r
+    } else {
+        (n * r.ln_1p()).exp_m1() / r
+    }
+}
+
+// fv(rate) = 0 となる利率をニュートン法で求める
+pub fn rate(nper: f64, pmt: f64, pv: f64, fv: f64) -> Result<f64> {
+    let scale = pv.abs() + (pmt * nper).abs() + fv.abs();
+    // 利息が無くても釣り合う(無利息のローンなど)
+    if (pv + pmt * nper + fv).abs() <= TOLERANCE * scale {
+        return Ok(0.0);
+    }
+    let f = |r: f64| pv * (nper * r.ln_1p()).exp() + pmt * annuity_factor(r, nper) + fv;
+    newton(f, 0.1, scale).ok_or_else(|| "rate did not converge".to_string())
+}
+
+pub fn npv(rate: f64, flows: &[f64]) -> f64 {
+    flows
+        .iter()
+        .enumerate()
+        .map(|(i, cf)| cf / (1.0 + rate).powi(i as i32 + 1))
+        .sum()
+}
+
+pub fn irr(flows: &[f64]) -> Result<f64> {
+    if !(flows.iter().any(|&cf| cf > 0.0) && flows.iter().any(|&cf| cf < 0.0)) {
+        return Err("irr needs both positive and negative cash flows".to_string());
+    }
+    // npvは1期後から割り引くので､現時点のcf0は別に足す
+    let f = |r: f64| flows[0] + npv(r, &flows[1..]);
+    let scale = flows.iter().map(|cf| cf.abs()).sum();
+    newton(f, 0.1, scale).ok_or_else(|| "irr did not converge".to_string())
+}
+
+pub fn compound(principal: f64, rate: f64, n: f64, years: f64) -> Result<f64> {
+    if n <= 0.0 {
+        return Err("compounding count must be positive".to_string());
+    }
+    Ok(principal * (1.0 + rate / n).powf(n * years))
+}
+
+pub fn continuous(principal: f64, rate: f64, years: f64) -> f64 {
+    principal * (rate * years).exp()
+}
+
+// 導関数は数値微分で近似する. scaleは金額の大きさ(許容誤差をこれに対する比にする)
+fn newton<F: Fn(f64) -> f64>(f: F, guess: f64, scale: f64) -> Option<f64> {
+    let tolerance = TOLERANCE * scale.max(1.0);
+    let mut x = guess;
+    for _ in 0..MAX_ITERATIONS {
+        let y = f(x);
+        if y.abs() < tolerance {
+            return Some(x);
+        }
+        let h = 1e-7;
+        let dy = (f(x + h) - f(x - h)) / (2.0 * h);
+        if dy == 0.0 || !dy.is_finite() {
+            return None;
+        }
+        let next = x - y / dy;
+        // 利率は-100%を下回れない
+        x = if next <= -1.0 { (x - 1.0) / 2.0 } else { next };
+        if !x.is_finite() {
+            return None;
+        }
+    }
+    (f(x).abs() < 1e-6 * scale.max(1.0)).then_some(x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(x: f64, y: f64) -> bool {
+        (x - y).abs() < 1e-6
+    }
+
+    #[test]
+    fn test_tvm() {
+        // 表計算ソフトの値と比較する
+        assert!(close(fv(0.05, 10.0, -100.0, -1000.0), 2_886.683_880_332_326));
+        assert!(close(pv(0.08 / 12.0, 240.0, 500.0, 0.0), -59_777.145_851_187_8));
+        assert!(close(pmt(0.08 / 12.0, 10.0, 10000.0, 0.0).unwrap(), -1_037.032_089_359_164_4));
+        assert!(close(nper(0.01, -100.0, -1000.0, 10000.0).unwrap(), 60.082_122_853_761_66));
+        assert!(close(rate(4.0 * 12.0, -200.0, 8000.0, 0.0).unwrap(), 0.007_701_472_488_246_008));
+        assert!(close(fv(0.0, 10.0, -100.0, 0.0), 1000.0));
+    }
+
+    // 無利息や0に近い利率でも収束する
+    #[test]
+    fn test_rate_near_zero() {
+        assert_eq!(rate(10.0, -100.0, 1000.0, 0.0).unwrap(), 0.0);
+        assert_eq!(rate(12.0, -100.0, 1200.0, 0.0).unwrap(), 0.0);
+        assert!((rate(12.0, -100.0, 1199.0, 0.0).unwrap() - 0.000_128_281_887_075_700_2).abs() < 1e-12);
+        let r = 1e-8;
+        let payment = pmt(r, 360.0, 100_000.0, 0.0).unwrap();
+        assert!((rate(360.0, payment, 100_000.0, 0.0).unwrap() - r).abs() < 1e-10);
+        // 12 + 66r + 220r^2
+        assert!((annuity_factor(1e-9, 12.0) - (12.0 + 66e-9)).abs() < 1e-14);
+        assert!((annuity_factor(0.01, 12.0) - 12.682_503_013_196_972).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_cash_flows() {
+        let flows = [-10000.0, 3000.0, 4200.0, 6800.0];
+        assert!(close(npv(0.1, &flows[1..]) + flows[0], 1_307.287_753_568_744));
+        assert!(close(irr(&flows).unwrap(), 0.163_405_600_688_989_9));
+        assert!(irr(&[100.0, 200.0]).is_err());
+    }
+
+    #[test]
+    fn test_interest() {
+        assert!(close(compound(1000.0, 0.05, 12.0, 10.0).unwrap(), 1_647.009_497_690_283));
+        assert!(close(continuous(1000.0, 0.05, 10.0), 1_648.721_270_700_128));
+        assert!(compound(1000.0, 0.05, 0.0, 10.0).is_err());
+    }
+}
@@ -0,0 +1,118 @@
+use crate::error::CalcError;
+use crate::locale::{Lang, Numbers};
+use crate::number::Number;
+use crate::value::Value;
+
+// 結果の表示形式. radixは整数にだけ､precisionは小数にだけ効く
+// numbersは10進数の数値の書き方､langはエラーメッセージの言語
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Format {
+    pub radix: u32,
+    pub precision: Option<usize>,
+    pub numbers: Numbers,
+    pub lang: Lang,
+}
+
+pub const RADIXES: [u32; 4] = [2, 8, 10, 16];
+
+impl Default for Format {
+    fn default() -> Self {
+        Format {
+            radix: 10,
+            precision: None,
+            numbers: Numbers::C,
+            lang: Lang::En,
+        }
+    }
+}
+
+impl Format {
+    pub fn number(&self, x: Number) -> String {
+        match x {
+            Number::Int(x) => self.int(x),
+            Number::Float(x) => self.float(x),
+        }
+    }
+
+    // 10進以外は 0xff や -0b101 のように接頭辞を付ける
+    fn int(&self, x: i64) -> String {
+        let sign = if x < 0 { "-" } else { "" };
+        let abs = x.unsigned_abs();
+        match self.radix {
+            2 => format!("{}0b{:b}", sign, abs),
+            8 => format!("{}0o{:o}", sign, abs),
+            16 => format!("{}0x{:x}", sign, abs),
+            _ => self.numbers.localize(&x.to_string()),
+        }
+    }
+
+    fn float(&self, x: f64) -> String {
+        let plain = match self.precision {
+            Some(precision) if x.is_finite() => format!("{:.*}", precision, x),
+            _ => x.to_string(),
+        };
+        self.numbers.localize(&plain)
+    }
+
+    pub fn value(&self, value: &Value) -> String {
+        match value {
+            Value::Num(x) => self.number(*x),
+            Value::List(values) => {
+                let values = values
+                    .iter()
+                    .map(|v| match v {
+                        Value::Str(s) => format!("{:?}", s),
+                        v => self.value(v),
+                    })
+                    .collect::<Vec<_>>();
+                format!("[{}]", values.join(" "))
+            }
+            Value::Quantity(q) => format!("{} {}", self.float(q.value), q.unit),
+            // "3.20+-0.10" の中央値と誤差をそれぞれ変換する. "[-inf, inf]" の形はそのまま
+            Value::Interval(x) if self.numbers != Numbers::C => {
+                let text = x.to_string();
+                match text.split_once("+-") {
+                    Some((mid, error)) => format!("{}+-{}", self.numbers.localize(mid), self.numbers.localize(error)),
+                    None => text,
+                }
+            }
+            other => other.to_string(),
+        }
+    }
+
+    pub fn error(&self, e: &CalcError) -> String {
+        e.message(self.lang)
+    }
+
+    // stack-policy = "all" の場合は複数の値を空白区切りで並べる
+    pub fn values(&self, values: &[Value]) -> String {
+        values.iter().map(|v| self.value(v)).collect::<Vec<_>>().join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        let hex = Format {
+            radix: 16,
+            precision: Some(2),
+            ..Format::default()
+        };
+        assert_eq!(hex.number(Number::Int(255)), "0xff");
+        assert_eq!(hex.number(Number::Int(-5)), "-0x5");
+        assert_eq!(hex.number(Number::Int(i64::MIN)), "-0x8000000000000000");
+        assert_eq!(hex.number(Number::Float(1.0 / 3.0)), "0.33");
+        let list = Value::List(vec![Value::Num(Number::Int(10)), Value::Num(Number::Float(2.5))]);
+        assert_eq!(hex.value(&list), "[0xa 2.50]");
+        assert_eq!(Format::default().values(&[list.clone(), list.clone()]), "[10 2.5] [10 2.5]");
+        let de = Format {
+            numbers: Numbers::De,
+            ..Format::default()
+        };
+        assert_eq!(de.value(&list), "[10 2,5]");
+        assert_eq!(de.number(Number::Float(-1234567.25)), "-1.234.567,25");
+    }
+}
@@ -0,0 +1,194 @@
+use crate::calculator::RpnCalculator;
+use crate::script::{Line, Script};
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+// .rpnファイル中の期待値の注釈
+// "# expect: 42" または "# expect-error: StackUnderflow" の形で書く
+#[derive(Debug, PartialEq)]
+enum Expect {
+    Value(String),
+    Error(String),
+}
+
+impl Expect {
+    fn parse(comment: &str) -> Option<Self> {
+        // "expect-error:" も "expect" で始まるので先に判定する
+        if let Some(kind) = comment.strip_prefix("expect-error:") {
+            Some(Expect::Error(kind.trim().to_string()))
+        } else {
+            comment
+                .strip_prefix("expect:")
+                .map(|value| Expect::Value(value.trim().to_string()))
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Expect::Value(value) => value.clone(),
+            Expect::Error(kind) => format!("error: {}", kind),
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Summary {
+    pub files: usize,
+    pub passed: usize,
+    pub failed: usize,
+}
+
+// 指定されたディレクトリ(またはファイル)以下の.rpnファイルを全て検査する
+// 乱数を使う式の結果も安定するよう､種が指定されなければ0を使う
+pub fn run<W: Write>(paths: &[PathBuf], seed: Option<u64>, out: &mut W) -> Result<Summary> {
+    let mut files = Vec::new();
+    for path in paths {
+        collect_files(path, &mut files)?;
+    }
+
+    let mut summary = Summary::default();
+    for file in &files {
+        let script = Script::open(file);
+        let calculator = RpnCalculator::new(false).with_seed(seed.unwrap_or(0));
+        check_file(&file.display().to_string(), script, &calculator, out, &mut summary)?;
+    }
+    writeln!(
+        out,
+        "test result: {} passed; {} failed ({} files)",
+        summary.passed, summary.failed, summary.files
+    )?;
+    Ok(summary)
+}
+
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    if !path.is_dir() {
+        // 直接指定されたファイルは拡張子に関係なく対象にする
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+    let mut entries = fs::read_dir(path)
+        .with_context(|| format!("failed to read directory {}", path.display()))?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("failed to read directory {}", path.display()))?;
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir() {
+            collect_files(&entry, files)?;
+        } else if entry.extension().is_some_and(|ext| ext == "rpn") {
+            files.push(entry);
+        }
+    }
+    Ok(())
+}
+
+// 1ファイル分の検査. 注釈の無い行も評価はする
+fn check_file<W: Write>(
+    name: &str,
+    script: Result<Script>,
+    calculator: &RpnCalculator,
+    out: &mut W,
+    summary: &mut Summary,
+) -> Result<()> {
+    let mut passed = 0;
+    let mut failures = Vec::new();
+
+    let lines = script.and_then(|script| script.collect::<Result<Vec<Line>>>());
+    match lines {
+        Ok(lines) => {
+            for line in lines {
+                let expects = line
+                    .comments
+                    .iter()
+                    .filter_map(|comment| Expect::parse(comment))
+                    .collect::<Vec<_>>();
+                let actual = match calculator.eval(&line.text) {
+                    Ok(answer) => Expect::Value(answer.to_string()),
+                    Err(e) => Expect::Error(e.kind().to_string()),
+                };
+                for expect in expects {
+                    if expect == actual {
+                        passed += 1;
+                    } else {
+                        failures.push(format!(
+                            "--- {}:{}: {}\n- {}\n+ {}",
+                            line.source,
+                            line.number,
+                            line.text,
+                            expect.describe(),
+                            actual.describe()
+                        ));
+                    }
+                }
+            }
+        }
+        // includeの失敗などでファイルを読めなかった場合は､そのファイル全体を失敗とする
+        Err(e) => failures.push(format!("--- {}\n{:#}", name, e)),
+    }
+
+    if passed == 0 && failures.is_empty() {
+        // 注釈の無いファイルは対象外
+        return Ok(());
+    }
+    summary.files += 1;
+    summary.passed += passed;
+    summary.failed += failures.len();
+    if failures.is_empty() {
+        writeln!(out, "ok   {} ({} passed)", name, passed)?;
+    } else {
+        writeln!(out, "FAIL {} ({} passed, {} failed)", name, passed, failures.len())?;
+        for failure in failures {
+            writeln!(out, "{}", failure)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn check(input: &str) -> (Summary, String) {
+        let script = Script::new(Box::new(Cursor::new(input.to_string())), "test");
+        let mut summary = Summary::default();
+        let mut out = Vec::new();
+        let calculator = RpnCalculator::new(false).with_seed(0);
+        check_file("test", Ok(script), &calculator, &mut out, &mut summary).unwrap();
+        (summary, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_parse_expect() {
+        assert_eq!(Expect::parse("expect: 42"), Some(Expect::Value("42".into())));
+        assert_eq!(
+            Expect::parse("expect-error: StackUnderflow"),
+            Some(Expect::Error("StackUnderflow".into()))
+        );
+        assert_eq!(Expect::parse("just a comment"), None);
+    }
+
+    #[test]
+    fn test_mismatch() {
+        let (summary, out) = check("1 2 + # expect: 3\n# expect: 4\n1 2 +\n1 + # expect-error: StackUnderflow\n");
+        assert_eq!(
+            summary,
+            Summary {
+                files: 1,
+                passed: 2,
+                failed: 1
+            }
+        );
+        assert!(out.contains("--- test:3: 1 2 +\n- 4\n+ 3"), "{}", out);
+    }
+
+    #[test]
+    fn test_assets() {
+        let mut out = Vec::new();
+        let summary = run(&[PathBuf::from("assets/golden")], None, &mut out).unwrap();
+        assert_eq!(summary.failed, 0, "{}", String::from_utf8(out).unwrap());
+        assert!(summary.passed > 0);
+    }
+}
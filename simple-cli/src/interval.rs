@@ -0,0 +1,211 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+// 区間演算. 真の値が必ず[lo, hi]に入るように､計算のたびに端を外側へ1ulpずつ広げる
+//
+//   3.2+-0.1   中央値と誤差で書いたリテラル([3.1, 3.3]を少し広げたもの)
+//   lo / hi    ( x -- lo ) ( x -- hi )  区間の端を取り出す
+//
+// --mode interval(--interval) では小数のリテラルも区間になる(10進数の丸め誤差を含めるため)
+// ※ 整数のリテラルはもともと誤差が無いので区間にしない. ただし整数どうしの / は切り捨てず区間で計算する
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Interval {
+    pub lo: f64,
+    pub hi: f64,
+}
+
+// 2^53 を超える整数はf64で正確に表せない
+const EXACT_INT: f64 = 9_007_199_254_740_992.0;
+
+impl Interval {
+    pub fn new(lo: f64, hi: f64) -> Self {
+        Interval { lo, hi }
+    }
+
+    pub fn point(x: f64) -> Self {
+        Interval::new(x, x)
+    }
+
+    pub fn from_int(x: i64) -> Self {
+        let y = x as f64;
+        if y.abs() < EXACT_INT {
+            Interval::point(y)
+        } else {
+            Interval::point(y).widen()
+        }
+    }
+
+    // 3.2+-0.1 や､10進数から読んだ小数(読み込み時に丸められている)
+    pub fn around(mid: f64, radius: f64) -> Self {
+        Interval::new(mid - radius, mid + radius).widen()
+    }
+
+    fn widen(self) -> Self {
+        Interval::new(self.lo.next_down(), self.hi.next_up())
+    }
+
+    pub fn add(self, rhs: Interval) -> Interval {
+        Interval::new(self.lo + rhs.lo, self.hi + rhs.hi).widen()
+    }
+
+    pub fn sub(self, rhs: Interval) -> Interval {
+        Interval::new(self.lo - rhs.hi, self.hi - rhs.lo).widen()
+    }
+
+    pub fn mul(self, rhs: Interval) -> Interval {
+        // 0 * inf はNaNになるが､区間の端としては0とみなしてよい
+        let products = [
+            self.lo * rhs.lo,
+            self.lo * rhs.hi,
+            self.hi * rhs.lo,
+            self.hi * rhs.hi,
+        ]
+        .map(|x| if x.is_nan() { 0.0 } else { x });
+        hull(&products).widen()
+    }
+
+    // 0を含む区間で割ると結果は2つの区間に分かれうるので､それらを包む1つの区間を返す
+    // 割る数が[0, 0]の場合だけはエラー(None)
+    pub fn div(self, rhs: Interval) -> Option<Interval> {
+        let (a, b, c, d) = (self.lo, self.hi, rhs.lo, rhs.hi);
+        if !rhs.contains(0.0) {
+            return Some(hull(&[a / c, a / d, b / c, b / d]).widen());
+        }
+        if c == 0.0 && d == 0.0 {
+            return None;
+        }
+        let res = match (c == 0.0, d == 0.0) {
+            _ if a <= 0.0 && 0.0 <= b => Interval::new(f64::NEG_INFINITY, f64::INFINITY),
+            // [c, d] = [0, d]
+            (true, _) if b < 0.0 => Interval::new(f64::NEG_INFINITY, b / d),
+            (true, _) => Interval::new(a / d, f64::INFINITY),
+            // [c, d] = [c, 0]
+            (_, true) if b < 0.0 => Interval::new(b / c, f64::INFINITY),
+            (_, true) => Interval::new(f64::NEG_INFINITY, a / c),
+            // 0を内側に含む場合は正負両方に発散する
+            _ => Interval::new(f64::NEG_INFINITY, f64::INFINITY),
+        };
+        Some(res.widen())
+    }
+
+    // 区間が重なっていると大小が決まらないのでNone
+    pub fn compare(self, rhs: Interval) -> Option<Ordering> {
+        if self.hi < rhs.lo {
+            Some(Ordering::Less)
+        } else if self.lo > rhs.hi {
+            Some(Ordering::Greater)
+        } else if self.lo == self.hi && self == rhs {
+            Some(Ordering::Equal)
+        } else {
+            None
+        }
+    }
+
+    pub fn contains(self, x: f64) -> bool {
+        self.lo <= x && x <= self.hi
+    }
+}
+
+fn hull(values: &[f64]) -> Interval {
+    let lo = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let hi = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    Interval::new(lo, hi)
+}
+
+// 端や中央値の表示. 1e300 のような数は{}では数百桁になるので､極端な大きさのものは指数表記にする
+fn number(x: f64) -> String {
+    if x.is_finite() && x != 0.0 && !(1e-6..1e16).contains(&x.abs()) {
+        format!("{:e}", x)
+    } else {
+        x.to_string()
+    }
+}
+
+// "3.20+-0.11" のように中央値と誤差で表示する. 誤差は有効数字2桁に切り上げ､
+// 中央値はその桁に丸めて､丸めた分も誤差に足す(表示した範囲が元の区間を含むようにする)
+// ※ 端を広げた1ulp程度の差は表示では無視する
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (lo, hi) = (self.lo, self.hi);
+        if lo == hi {
+            return write!(f, "{}", number(lo));
+        }
+        let mid = lo / 2.0 + hi / 2.0;
+        let radius = (hi - mid).max(mid - lo);
+        if !mid.is_finite() || !radius.is_finite() || radius == 0.0 {
+            return write!(f, "[{}, {}]", number(lo), number(hi));
+        }
+        let exp = radius.log10().floor() as i32 - 1;
+        let scale = 10f64.powi(exp);
+        let shown = (mid / scale).round() * scale;
+        let error = ((radius + (mid - shown).abs()) / scale * (1.0 - 1e-12)).ceil() * scale;
+        let decimals = (-exp).max(0) as usize;
+        // 丸め誤差だけの細い区間は桁が多くなりすぎるので､中央値をそのまま出して誤差を指数表記にする
+        if decimals > 12 {
+            let error = (radius / scale * (1.0 - 1e-12)).ceil() * scale;
+            return write!(f, "{}+-{:.1e}", number(mid), error);
+        }
+        // 誤差の桁が大きい場合も整数部が長くなりすぎるので両方を指数表記にする
+        // ※ 中央値の仮数部は誤差の桁までにする
+        if shown.abs() >= 1e16 || error >= 1e16 {
+            let digits = (mid.abs().log10().floor() as i32 - exp).clamp(0, 16) as usize;
+            return write!(f, "{:.*e}+-{:.1e}", digits, mid, error);
+        }
+        write!(f, "{:.*}+-{:.*}", decimals, shown, decimals, error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arithmetic() {
+        let x = Interval::around(3.2, 0.1);
+        let y = Interval::around(2.0, 0.5);
+        assert_eq!(x.to_string(), "3.20+-0.10");
+        assert_eq!(x.add(y).to_string(), "5.20+-0.60");
+        assert_eq!(x.sub(y).to_string(), "1.20+-0.60");
+        let p = x.mul(y);
+        assert!(p.lo <= 4.65 && p.hi >= 8.25 && p.hi < 8.2501, "{:?}", p);
+        let q = Interval::point(1.0).div(Interval::point(3.0)).unwrap();
+        assert!(q.contains(1.0 / 3.0) && q.lo < q.hi);
+        // 0.1 + 0.2 は浮動小数点では0.30000000000000004だが､区間は0.3を含む
+        let sum = Interval::around(0.1, 0.0).add(Interval::around(0.2, 0.0));
+        assert!(sum.contains(0.3));
+        assert_eq!(Interval::from_int(2).mul(Interval::point(-1.5)), Interval::point(-3.0).widen());
+        assert_eq!(Interval::around(2.5, 0.0).to_string(), "2.5+-4.5e-16");
+    }
+
+    #[test]
+    fn test_div_zero() {
+        let one = Interval::new(1.0, 2.0);
+        assert_eq!(one.div(Interval::point(0.0)), None);
+        let r = one.div(Interval::new(0.0, 4.0)).unwrap();
+        assert!(r.lo <= 0.25 && r.lo > 0.2499 && r.hi == f64::INFINITY);
+        let r = one.div(Interval::new(-4.0, 0.0)).unwrap();
+        assert!(r.lo == f64::NEG_INFINITY && r.hi >= -0.25 && r.hi < -0.2499);
+        let r = one.div(Interval::new(-1.0, 1.0)).unwrap();
+        assert_eq!((r.lo, r.hi), (f64::NEG_INFINITY, f64::INFINITY));
+        assert_eq!(r.to_string(), "[-inf, inf]");
+    }
+
+    // 幅の広い区間や大きな端も数百桁にしない
+    #[test]
+    fn test_display_large() {
+        assert_eq!(Interval::around(1e300, 1e300).to_string(), "1.0e300+-1.0e300");
+        assert_eq!(Interval::new(1e300, f64::INFINITY).to_string(), "[1e300, inf]");
+        assert_eq!(Interval::point(-1e300).to_string(), "-1e300");
+        assert_eq!(Interval::new(f64::MAX, f64::INFINITY).to_string(), "[1.7976931348623157e308, inf]");
+        assert_eq!(Interval::around(1e20, 1e18).to_string(), "1.000e20+-1.0e18");
+    }
+
+    #[test]
+    fn test_compare() {
+        let x = Interval::around(3.0, 0.1);
+        assert_eq!(x.compare(Interval::point(4.0)), Some(Ordering::Less));
+        assert_eq!(x.compare(Interval::point(2.0)), Some(Ordering::Greater));
+        assert_eq!(x.compare(Interval::point(3.05)), None);
+        assert_eq!(Interval::point(1.0).compare(Interval::point(1.0)), Some(Ordering::Equal));
+    }
+}
@@ -0,0 +1,390 @@
+use crate::date::Date;
+use crate::error::CalcError;
+use crate::locale::Numbers;
+use crate::number::Number;
+
+// 字句解析. 空白がなくても数値と演算子を分ける("1 2 3++" => 1 2 3 + +)
+//
+//   数値      1  -2  .5  1e3   (符号はトークンの先頭にある場合だけ数値の一部)
+//   単位付き  90min  5km      (数値の直後の英字は単位. "90 min" のminは集計演算子のまま)
+//   日付      2026-10-18
+//   区間      3.2+-0.1         (中央値と誤差. 間に空白は入れられない)
+//   名前      dup  business-days  =rate  (英字の間の-は名前の一部)
+//   演算子    + - * / % < > <= >= == != ->
+//   文字列    "hello \"world\""
+//   括弧      [ ] { }
+//   コメント  ( ... )         (トークンには含めない)
+//
+// --numbers en/de では数値を 1,234.5 や 1.234,5 の書式で読む(locale.rs)
+#[derive(Clone, Debug, PartialEq)]
+pub enum Tok {
+    Num(Number),
+    Quantity(Number, String),
+    Date(Date),
+    Interval(Number, Number),
+    Word(String),
+    Str(String),
+    Open(char),
+    Close(char),
+}
+
+// posはトークンの番号(1始まり)､colは文字単位の桁(1始まり)､lenはトークンの文字数
+#[derive(Clone, Debug, PartialEq)]
+pub struct Token {
+    pub tok: Tok,
+    pub pos: usize,
+    pub col: usize,
+    pub len: usize,
+}
+
+const OPERATORS: [&str; 13] = ["<=", ">=", "==", "!=", "->", "+", "-", "*", "/", "%", "<", ">", "="];
+
+pub fn lex(formula: &str, numbers: Numbers) -> Result<Vec<Token>, CalcError> {
+    Lexer::new(formula, numbers).run()
+}
+
+// エラー表示用. pos番目のトークンの桁と文字数を返す
+pub fn span(formula: &str, pos: usize, numbers: Numbers) -> Option<(usize, usize)> {
+    let mut lexer = Lexer::new(formula, numbers);
+    lexer.lenient = true;
+    let tokens = lexer.run().ok()?;
+    tokens.iter().find(|t| t.pos == pos).map(|t| (t.col, t.len))
+}
+
+struct Lexer {
+    chars: Vec<char>,
+    i: usize,
+    tokens: Vec<Token>,
+    // trueなら閉じていない文字列やコメントを行末までとみなす
+    lenient: bool,
+    numbers: Numbers,
+}
+
+impl Lexer {
+    fn new(formula: &str, numbers: Numbers) -> Self {
+        Lexer {
+            chars: formula.chars().collect(),
+            i: 0,
+            tokens: Vec::new(),
+            lenient: false,
+            numbers,
+        }
+    }
+
+    fn peek(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.i + offset).copied()
+    }
+
+    fn next_pos(&self) -> usize {
+        self.tokens.len() + 1
+    }
+
+    fn push(&mut self, tok: Tok, start: usize) {
+        let pos = self.next_pos();
+        self.tokens.push(Token {
+            tok,
+            pos,
+            col: start + 1,
+            len: self.i - start,
+        });
+    }
+
+    fn run(mut self) -> Result<Vec<Token>, CalcError> {
+        while let Some(c) = self.peek(0) {
+            let start = self.i;
+            match c {
+                c if c.is_whitespace() => self.i += 1,
+                '(' => self.comment()?,
+                '"' => {
+                    let s = self.string()?;
+                    self.push(Tok::Str(s), start);
+                }
+                '[' | '{' => {
+                    self.i += 1;
+                    self.push(Tok::Open(c), start);
+                }
+                ']' | '}' => {
+                    self.i += 1;
+                    self.push(Tok::Close(c), start);
+                }
+                _ if self.date_len().is_some() => self.date()?,
+                _ if self.number_len().is_some() => self.number(),
+                c if is_word_start(c) => {
+                    let word = self.word();
+                    self.push(Tok::Word(word), start);
+                }
+                // =rate のような変数の束縛
+                '=' if self.peek(1).is_some_and(is_word_start) => {
+                    self.i += 1;
+                    let word = format!("={}", self.word());
+                    self.push(Tok::Word(word), start);
+                }
+                _ => {
+                    let op = self.operator();
+                    self.push(Tok::Word(op), start);
+                }
+            }
+        }
+        Ok(self.tokens)
+    }
+
+    // 入れ子にはしない. 閉じていなければエラー
+    fn comment(&mut self) -> Result<(), CalcError> {
+        match self.chars[self.i..].iter().position(|&c| c == ')') {
+            Some(n) => self.i += n + 1,
+            None if self.lenient => self.i = self.chars.len(),
+            None => return Err(CalcError::UnterminatedComment { pos: self.next_pos() }),
+        }
+        Ok(())
+    }
+
+    // \" \\ \n \t をエスケープとして扱う
+    fn string(&mut self) -> Result<String, CalcError> {
+        let mut s = String::new();
+        self.i += 1;
+        loop {
+            match self.peek(0) {
+                None if self.lenient => return Ok(s),
+                None => return Err(CalcError::UnterminatedString { pos: self.next_pos() }),
+                Some('"') => {
+                    self.i += 1;
+                    return Ok(s);
+                }
+                Some('\\') => {
+                    s.push(match self.peek(1) {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some(c) => c,
+                        None => '\\',
+                    });
+                    self.i += 2;
+                }
+                Some(c) => {
+                    s.push(c);
+                    self.i += 1;
+                }
+            }
+        }
+    }
+
+    // 数値や日付はトークンの先頭からしか始まらない
+    fn at_start(&self) -> bool {
+        self.i == 0 || {
+            let prev = self.chars[self.i - 1];
+            prev.is_whitespace() || matches!(prev, '[' | '{' | ')')
+        }
+    }
+
+    // YYYY-MM-DD の形なら10を返す. 後ろに英数字が続く場合は日付にしない
+    fn date_len(&self) -> Option<usize> {
+        let digit = |k: usize| self.peek(k).is_some_and(|c| c.is_ascii_digit());
+        let shape = (0..10).all(|k| if k == 4 || k == 7 { self.peek(k) == Some('-') } else { digit(k) });
+        let end = self.peek(10).is_some_and(is_word_char);
+        (self.at_start() && shape && !end).then_some(10)
+    }
+
+    // 2023-02-29 のような存在しない日付はエラー
+    fn date(&mut self) -> Result<(), CalcError> {
+        let start = self.i;
+        let text = self.chars[start..start + 10].iter().collect::<String>();
+        self.i += 10;
+        match Date::parse(&text) {
+            Some(date) => self.push(Tok::Date(date), start),
+            None if self.lenient => self.push(Tok::Word(text), start),
+            None => {
+                return Err(CalcError::InvalidArgument {
+                    pos: self.next_pos(),
+                    reason: format!("invalid date {}", text),
+                })
+            }
+        }
+        Ok(())
+    }
+
+    // 現在位置から数値が始まっていればその文字数を返す
+    fn number_len(&self) -> Option<usize> {
+        let at_start = self.at_start();
+        let mut n = 0;
+        if at_start && matches!(self.peek(0), Some('+' | '-')) {
+            n += 1;
+        }
+        let digits = |n: usize| {
+            (n..)
+                .take_while(|&k| self.peek(k).is_some_and(|c| c.is_ascii_digit()))
+                .count()
+        };
+        let mut int = digits(n);
+        // 1,234,567 のような桁区切り. 区切りの後にちょうど3桁ある間だけ続ける
+        if let Some(group) = self.numbers.group().filter(|_| (1..=3).contains(&int)) {
+            while self.peek(n + int) == Some(group) && digits(n + int + 1) == 3 {
+                int += 4;
+            }
+        }
+        n += int;
+        let mut frac = 0;
+        if self.peek(n) == Some(self.numbers.decimal()) {
+            frac = digits(n + 1);
+            if int > 0 || frac > 0 {
+                n += 1 + frac;
+            }
+        }
+        if int == 0 && frac == 0 {
+            return None;
+        }
+        // 1e3 や 2.5E-4. eの後に数字が無ければ単位(1em など)として扱う
+        if matches!(self.peek(n), Some('e' | 'E')) {
+            let sign = usize::from(matches!(self.peek(n + 1), Some('+' | '-')));
+            let exp = digits(n + 1 + sign);
+            if exp > 0 {
+                n += 1 + sign + exp;
+            }
+        }
+        Some(n)
+    }
+
+    fn number(&mut self) {
+        let start = self.i;
+        let n = self.number_len().unwrap();
+        let text = self.chars[start..start + n].iter().collect::<String>();
+        let text = self.numbers.delocalize(&text);
+        // 数字しか含まないので必ず読める
+        let x = Number::parse(&text).unwrap();
+        self.i += n;
+        if self.peek(0) == Some('+') && self.peek(1) == Some('-') {
+            self.i += 2;
+            match self.number_len() {
+                Some(n) => {
+                    let text = self.chars[self.i..self.i + n].iter().collect::<String>();
+                    let text = self.numbers.delocalize(&text);
+                    self.i += n;
+                    self.push(Tok::Interval(x, Number::parse(&text).unwrap()), start);
+                    return;
+                }
+                // 3+- のように誤差が無ければ､ただの数値と演算子として読み直す
+                None => self.i -= 2,
+            }
+        }
+        if self.peek(0).is_some_and(|c| c.is_alphabetic()) {
+            let unit = self.word();
+            self.push(Tok::Quantity(x, unit), start);
+        } else {
+            self.push(Tok::Num(x), start);
+        }
+    }
+
+    // 英字の間に挟まれた-は名前の一部にする(business-days)
+    fn word(&mut self) -> String {
+        let start = self.i;
+        while let Some(c) = self.peek(0) {
+            let hyphen = c == '-'
+                && self.i > start
+                && self.chars[self.i - 1].is_alphabetic()
+                && self.peek(1).is_some_and(char::is_alphabetic);
+            if is_word_char(c) || hyphen {
+                self.i += 1;
+            } else {
+                break;
+            }
+        }
+        self.chars[start..self.i].iter().collect()
+    }
+
+    // 長いものから試す. どれにも当たらなければ1文字を未知のトークンにする
+    fn operator(&mut self) -> String {
+        let rest = self.chars[self.i..].iter().take(2).collect::<String>();
+        let op = OPERATORS
+            .iter()
+            .find(|op| rest.starts_with(*op))
+            .map_or_else(|| rest.chars().take(1).collect(), |op| op.to_string());
+        self.i += op.chars().count();
+        op
+    }
+}
+
+fn is_word_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_'
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(formula: &str) -> Vec<String> {
+        lex(formula, Numbers::C)
+            .unwrap()
+            .into_iter()
+            .map(|t| match t.tok {
+                Tok::Num(x) => x.to_string(),
+                Tok::Quantity(x, unit) => format!("{}{}", x, unit),
+                Tok::Date(date) => format!("@{}", date),
+                Tok::Interval(x, r) => format!("{}+-{}", x, r),
+                Tok::Word(w) => w,
+                Tok::Str(s) => format!("{:?}", s),
+                Tok::Open(c) | Tok::Close(c) => c.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_unspaced() {
+        assert_eq!(texts("2 3+"), vec!["2", "3", "+"]);
+        assert_eq!(texts("1 2 3++"), vec!["1", "2", "3", "+", "+"]);
+        assert_eq!(texts("5 -3 -"), vec!["5", "-3", "-"]);
+        assert_eq!(texts("[1 2]{2*}map"), vec!["[", "1", "2", "]", "{", "2", "*", "}", "map"]);
+        assert_eq!(texts("1 2<=3 4!="), vec!["1", "2", "<=", "3", "4", "!="]);
+        assert_eq!(texts("1e3 .5 -2.5E-1"), vec!["1000", "0.5", "-0.25"]);
+        assert_eq!(texts("3.2+-0.1 -5+-1e-3 2 3+-"), vec!["3.2+-0.1", "-5+-0.001", "2", "3", "+", "-"]);
+    }
+
+    #[test]
+    fn test_words() {
+        assert_eq!(texts("90min 5 km"), vec!["90min", "5", "km"]);
+        assert_eq!(texts("business-days 3 =x x-1"), vec!["business-days", "3", "=x", "x", "-", "1"]);
+        assert_eq!(texts("5000 m km ->"), vec!["5000", "m", "km", "->"]);
+        assert_eq!(texts("2026-10-18 3d+"), vec!["@2026-10-18", "3d", "+"]);
+        assert_eq!(texts("2026-10-18x 2026 -10"), vec!["2026", "-", "10", "-", "18x", "2026", "-10"]);
+        assert_eq!(lex("1 2026-02-30", Numbers::C).unwrap_err().kind(), "InvalidArgument");
+    }
+
+    #[test]
+    fn test_numbers() {
+        let values = |formula: &str, numbers: Numbers| {
+            lex(formula, numbers)
+                .unwrap()
+                .into_iter()
+                .map(|t| match t.tok {
+                    Tok::Num(x) => x.to_string(),
+                    Tok::Word(w) => w,
+                    _ => unreachable!(),
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(values("1,234,567.5 -2,000 +", Numbers::En), vec!["1234567.5", "-2000", "+"]);
+        assert_eq!(values("1.234,5 0,25 ,5", Numbers::De), vec!["1234.5", "0.25", "0.5"]);
+        // 3桁ごとになっていない区切りは数値に含めない
+        assert_eq!(values("1,23 1234,567", Numbers::En), vec!["1", ",", "23", "1234", ",", "567"]);
+        assert_eq!(values("1.5", Numbers::De), vec!["1", ".", "5"]);
+    }
+
+    #[test]
+    fn test_strings_comments() {
+        assert_eq!(texts(r#""a b" "say \"hi\"""#), vec![r#""a b""#, r#""say \"hi\"""#]);
+        assert_eq!(texts("1 ( a b -- c ) 2"), vec!["1", "2"]);
+        assert_eq!(lex("\"abc", Numbers::C), Err(CalcError::UnterminatedString { pos: 1 }));
+        assert_eq!(lex("1 ( abc", Numbers::C), Err(CalcError::UnterminatedComment { pos: 2 }));
+    }
+
+    #[test]
+    fn test_span() {
+        let tokens = lex("1  23+ \"x\"", Numbers::C).unwrap();
+        let spans = tokens.iter().map(|t| (t.pos, t.col, t.len)).collect::<Vec<_>>();
+        assert_eq!(spans, vec![(1, 1, 1), (2, 4, 2), (3, 6, 1), (4, 8, 3)]);
+        assert_eq!(span("1 0 /", 3, Numbers::C), Some((5, 1)));
+        assert_eq!(span("1 \"abc", 2, Numbers::C), Some((3, 4)));
+        assert_eq!(span("1,5 0 /", 3, Numbers::De), Some((7, 1)));
+    }
+}
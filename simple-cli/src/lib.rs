@@ -0,0 +1,7 @@
+// src/bin/ のエラー処理のサンプルで共有するモジュール
+// ※ rpncalc本体(main.rs)が使うのはjsonだけ
+pub mod exit;
+pub mod json;
+pub mod number_source;
+pub mod panic_boundary;
+pub mod report;
@@ -0,0 +1,103 @@
+use clap::ArgEnum;
+use serde::Deserialize;
+use std::env;
+
+// エラーメッセージの言語. --lang か設定ファイルで指定が無ければ環境変数LANGで決める
+#[derive(ArgEnum, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Lang {
+    En,
+    Ja,
+}
+
+impl Lang {
+    // LANG=ja_JP.UTF-8 などなら日本語､それ以外は英語
+    pub fn from_env() -> Lang {
+        match env::var("LANG") {
+            Ok(lang) if lang.starts_with("ja") => Lang::Ja,
+            _ => Lang::En,
+        }
+    }
+}
+
+// 数値の書き方. 入力と出力の両方に効く
+//
+//   c    1234567.5     (既定)
+//   en   1,234,567.5
+//   de   1.234.567,5
+//
+// ※ 区切り文字に空白を使う書式(1 234,5)は字句解析の区切りとぶつかるので用意しない
+// ※ 入力の桁区切りは3桁ごとに正しく入っている場合だけ数値の一部とみなす("1,23" はエラー)
+#[derive(ArgEnum, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Numbers {
+    #[default]
+    C,
+    En,
+    De,
+}
+
+impl Numbers {
+    pub fn decimal(self) -> char {
+        match self {
+            Numbers::De => ',',
+            _ => '.',
+        }
+    }
+
+    pub fn group(self) -> Option<char> {
+        match self {
+            Numbers::C => None,
+            Numbers::En => Some(','),
+            Numbers::De => Some('.'),
+        }
+    }
+
+    // "-1234567.5" のような書式を変換する. 数字以外(infやNaN)はそのまま
+    pub fn localize(self, plain: &str) -> String {
+        let (sign, body) = match plain.strip_prefix('-') {
+            Some(body) => ("-", body),
+            None => ("", plain),
+        };
+        let (int, frac) = body.split_once('.').map_or((body, None), |(int, frac)| (int, Some(frac)));
+        if self == Numbers::C || !int.bytes().all(|b| b.is_ascii_digit()) {
+            return plain.to_string();
+        }
+        let mut out = sign.to_string();
+        for (i, c) in int.chars().enumerate() {
+            if i > 0 && (int.len() - i).is_multiple_of(3) {
+                out.extend(self.group());
+            }
+            out.push(c);
+        }
+        if let Some(frac) = frac {
+            out.push(self.decimal());
+            out += frac;
+        }
+        out
+    }
+
+    // 字句解析で切り出した数値を Number::parse で読める形に戻す
+    pub fn delocalize(self, text: &str) -> String {
+        text.chars()
+            .filter(|&c| Some(c) != self.group())
+            .map(|c| if c == self.decimal() { '.' } else { c })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_localize() {
+        assert_eq!(Numbers::C.localize("-1234567.5"), "-1234567.5");
+        assert_eq!(Numbers::En.localize("-1234567.5"), "-1,234,567.5");
+        assert_eq!(Numbers::De.localize("1234567.5"), "1.234.567,5");
+        assert_eq!(Numbers::De.localize("123"), "123");
+        assert_eq!(Numbers::En.localize("inf"), "inf");
+        assert_eq!(Numbers::De.delocalize("1.234,5"), "1234.5");
+        assert_eq!(Numbers::En.delocalize("1,234.5"), "1234.5");
+    }
+}
@@ -1,8 +1,41 @@
-use anyhow::{bail, ensure, Context, Result};
-use clap::Parser;
+mod batch;
+mod calculator;
+mod codegen;
+mod config;
+mod date;
+mod error;
+mod finance;
+mod format;
+mod golden;
+mod interval;
+mod lexer;
+mod locale;
+mod matrix;
+mod number;
+mod parser;
+mod plot;
+mod random;
+mod script;
+mod server;
+mod stats;
+mod text;
+mod units;
+mod value;
+mod watch;
+
+use anyhow::{anyhow, bail, Result};
+use calculator::{Limits, NumberMode, RpnCalculator, StackPolicy};
+use clap::{Args, Parser, Subcommand};
+use config::Config;
+use format::Format;
+use locale::Numbers;
+use script::Script;
 use std::fs::File;
-use std::path::PathBuf;
-use std::io::{stdin, BufRead, BufReader};
+use std::io::{stderr, stdin, stdout, BufReader, BufWriter, Cursor};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Duration;
 
 #[derive(Parser, Debug)]
 #[clap(
@@ -15,118 +48,449 @@ struct Opts {
     #[clap(short, long)]
     verbose: bool,
 
+    // 結果の前に入力元と行番号を付ける
+    #[clap(short, long)]
+    label: bool,
+
+    // -e は複数回指定できる. "-5 3 +" のような負数始まりも受けられるようにしておく
+    #[clap(short = 'e', long = "expression", allow_hyphen_values = true)]
+    expressions: Vec<String>,
+
+    // 乱数の種. 指定すると rand などの結果が毎回同じになる
+    #[clap(long, global = true)]
+    seed: Option<u64>,
+
+    // 設定ファイル. 省略時は ~/.config/rpncalc/config.toml があれば読む
+    #[clap(long, global = true)]
+    config: Option<PathBuf>,
+
+    // 以下は設定ファイルの値を上書きする
+    #[clap(long, arg_enum)]
+    mode: Option<NumberMode>,
+
+    // --mode interval と同じ. 小数を誤差付きの区間として計算する
+    #[clap(long, conflicts_with = "mode")]
+    interval: bool,
+
+    #[clap(long, possible_values = ["2", "8", "10", "16"])]
+    radix: Option<u32>,
+
+    // 小数の表示桁数
+    #[clap(long)]
+    precision: Option<usize>,
+
+    #[clap(long, arg_enum)]
+    stack_policy: Option<StackPolicy>,
+
+    // 起動時に読み込む定義ファイル
+    #[clap(long)]
+    prelude: Option<PathBuf>,
+
+    // エラーメッセージの言語. 省略時は環境変数LANGで決める
+    #[clap(long, arg_enum)]
+    lang: Option<locale::Lang>,
+
+    // 数値の書き方. en は 1,234.5､de は 1.234,5 (入力と出力の両方)
+    #[clap(long, arg_enum)]
+    numbers: Option<Numbers>,
+
+    // 行を複数のスレッドで評価する. 出力は入力と同じ順番. 変数の束縛は使えない
+    #[clap(long)]
+    jobs: Option<usize>,
+
+    // 処理した行数と処理速度を最後に標準エラーに出す
+    #[clap(long)]
+    stats: bool,
+
+    // ファイルが保存されるたびに全行を評価し直し､前回の結果との差分を表示する
+    #[clap(long)]
+    watch: bool,
+
+    #[clap(flatten)]
+    limits: LimitArgs,
+
+    // "-" は標準入力を表す
     #[clap(name = "FILE")]
-    formula_file: Option<PathBuf>,
+    formula_files: Vec<PathBuf>,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
 }
 
-struct RpnCalculator(bool);
+// 1行の評価で使える資源の上限(calculator::Limits). 省略した上限はかけない(serveは既定値あり)
+#[derive(Args, Debug)]
+struct LimitArgs {
+    // 評価するトークン数. クォーテーションを繰り返し評価した分も数える
+    #[clap(long, global = true)]
+    max_steps: Option<usize>,
 
-impl RpnCalculator {
-    pub fn new(verbose: bool) -> Self {
-        Self(verbose)
-    }
-    pub fn eval(&self, formula: &str) -> Result<i32> {
-        let mut tokens = formula
-            .split_whitespace()
-            // popでstackの末尾から操作していくので､逆順にする
-            .rev()
-            // colletは､イテレータをコレクションに変換する
-            // _は､Rustコンパイラ側で適切な方に推論してくれる
-            .collect::<Vec<_>>();
-        self.eval_impl(&mut tokens)
-    }
+    // スタックに積める値の数
+    #[clap(long, global = true)]
+    max_stack: Option<usize>,
 
-    fn eval_impl(&self, tokens: &mut Vec<&str>) -> Result<i32> {
-        let mut stack = Vec::new();
-        let mut pos = 0;
-
-        while let Some(token) = tokens.pop() {
-            pos += 1;
-
-            if let Ok(x) = token.parse::<i32>() {
-                stack.push(x);
-            } else {
-                let y = stack.pop().context(format!("invalid syntax at {}", pos))?;
-                let x = stack.pop().context(format!("invalid syntax at {}", pos))?;
-                let res = match token {
-                    "+" => x + y,
-                    "-" => x - y,
-                    "*" => x * y,
-                    "/" => x / y,
-                    "%" => x % y,
-                    _ => bail!("invalid token at {}", pos),
-                };
-                stack.push(res);
-            }
+    // クォーテーションとリストの入れ子の深さ(再帰の深さ)
+    #[clap(long, global = true)]
+    max_depth: Option<usize>,
 
-            // verbose表示
-            if self.0 {
-                println!("{:?} {:?}", tokens, stack);
-            }
+    // 1行のトークン数
+    #[clap(long, global = true)]
+    max_tokens: Option<usize>,
+
+    // 数値リテラルとfmtの結果の桁数
+    #[clap(long, global = true)]
+    max_digits: Option<usize>,
+
+    // 1行で作るリストと文字列の大きさの合計(バイト)
+    #[clap(long, global = true)]
+    max_memory: Option<usize>,
+
+    // 1行の評価時間(ミリ秒)
+    #[clap(long, global = true)]
+    max_time: Option<u64>,
+}
+
+impl LimitArgs {
+    fn limits(&self) -> Limits {
+        Limits {
+            max_steps: self.max_steps,
+            max_stack: self.max_stack,
+            max_depth: self.max_depth,
+            max_tokens: self.max_tokens,
+            max_digits: self.max_digits,
+            max_memory: self.max_memory,
+            max_time: self.max_time.map(Duration::from_millis),
         }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    // "# expect: 値" や "# expect-error: 種類名" の注釈が付いた.rpnファイルを検査する
+    #[clap(about = "Run .rpn files annotated with expected results")]
+    Test {
+        #[clap(name = "DIR", required = true)]
+        paths: Vec<PathBuf>,
+    },
+    // 変数を動かしながら式を評価し､端末にグラフを描く
+    #[clap(about = "Plot a formula over a range of its variable")]
+    Plot {
+        #[clap(name = "FORMULA", allow_hyphen_values = true)]
+        formula: String,
+
+        // 動かす変数の名前
+        #[clap(long, default_value = "x")]
+        var: String,
+
+        #[clap(long, default_value = "-10", allow_hyphen_values = true)]
+        from: f64,
+
+        #[clap(long, default_value = "10", allow_hyphen_values = true)]
+        to: f64,
+
+        // 省略時はグラフの幅と同じ数だけ評価する
+        #[clap(long)]
+        samples: Option<usize>,
+
+        #[clap(long, default_value = "72")]
+        width: usize,
+
+        #[clap(long, default_value = "20")]
+        height: usize,
+
+        #[clap(long)]
+        csv: Option<PathBuf>,
+
+        #[clap(long)]
+        svg: Option<PathBuf>,
+    },
+    // 自由変数を引数に持つ関数と､インタプリタと突き合わせるテストを出力する
+    #[clap(about = "Generate a Rust or C function from a formula")]
+    Codegen {
+        #[clap(name = "FORMULA", allow_hyphen_values = true)]
+        formula: String,
+
+        #[clap(long, arg_enum)]
+        lang: codegen::Lang,
+
+        #[clap(long)]
+        name: String,
+
+        // 引数の型. intはi64でオーバーフローを検出し､floatはf64で計算する
+        #[clap(long, arg_enum, default_value = "int")]
+        mode: codegen::Mode,
+
+        // 省略時は標準出力に書く
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+    },
+    // 1行1式のTCPサービス. 接続ごとにスタックと変数を持ち､結果をJSONで1行ずつ返す
+    #[clap(about = "Serve line-oriented evaluation over TCP")]
+    Serve {
+        #[clap(long)]
+        port: u16,
+
+        #[clap(long, default_value = "127.0.0.1")]
+        bind: String,
+
+        // 1行の最大バイト数
+        #[clap(long, default_value = "4096")]
+        max_request: usize,
 
-        ensure!(stack.len() == 1, "invalid syntax");
-        Ok(stack[0])
+        // 同時に受ける接続の数
+        #[clap(long, default_value = "64")]
+        max_connections: usize,
+
+        // 何も届かない接続を切るまでの秒数
+        #[clap(long, default_value = "60")]
+        read_timeout: u64,
+    },
+}
+
+// 計算式の入力元
+enum Input {
+    Expression(usize, String),
+    Stdin,
+    File(PathBuf),
+}
+
+impl Input {
+    fn name(&self) -> String {
+        match self {
+            Input::Expression(n, _) => format!("-e#{}", n),
+            Input::Stdin => "<stdin>".to_string(),
+            Input::File(path) => path.display().to_string(),
+        }
     }
 }
 
 fn main() -> Result<()> {
     let opts = Opts::parse();
 
-    if let Some(path) = opts.formula_file {
-        let f = File::open(path).unwrap();
-        let reader = BufReader::new(f);
-        run(reader, opts.verbose)
-    } else {
-        // println!("No file is specified")
-        let stdin = stdin();
-        let reader = stdin.lock();
-        run(reader, opts.verbose)
+    match opts.command {
+        Some(Command::Test { paths }) => {
+            let summary = golden::run(&paths, opts.seed, &mut stdout())?;
+            if summary.failed > 0 {
+                process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(Command::Plot {
+            formula,
+            var,
+            from,
+            to,
+            samples,
+            width,
+            height,
+            csv,
+            svg,
+        }) => {
+            let plot_opts = plot::Options {
+                var,
+                from,
+                to,
+                samples: samples.unwrap_or(width),
+                width,
+                height,
+            };
+            let mut calcurator = RpnCalculator::new(false);
+            if let Some(seed) = opts.seed {
+                calcurator = calcurator.with_seed(seed);
+            }
+            let points = plot::sample(&calcurator, &formula, &plot_opts)?;
+            print!("{}", plot::render(&points, &plot_opts));
+            if let Some(path) = csv {
+                plot::write_csv(&points, &plot_opts.var, &mut BufWriter::new(File::create(path)?))?;
+            }
+            if let Some(path) = svg {
+                plot::write_svg(&points, &plot_opts, &mut BufWriter::new(File::create(path)?))?;
+            }
+            return Ok(());
+        }
+        Some(Command::Codegen {
+            formula,
+            lang,
+            name,
+            mode,
+            output,
+        }) => {
+            let code = codegen::generate(&formula, &name, lang, mode)?;
+            match output {
+                Some(path) => std::fs::write(path, code)?,
+                None => print!("{}", code),
+            }
+            return Ok(());
+        }
+        Some(Command::Serve {
+            port,
+            bind,
+            max_request,
+            max_connections,
+            read_timeout,
+        }) => {
+            let listener = TcpListener::bind((bind.as_str(), port))?;
+            eprintln!("listening on {}", listener.local_addr()?);
+            let serve_opts = server::Options {
+                max_request,
+                limits: opts.limits.limits().or(server::DEFAULT_LIMITS),
+                seed: opts.seed,
+                max_connections,
+                read_timeout: Duration::from_secs(read_timeout),
+            };
+            return server::run(listener, serve_opts);
+        }
+        None => {}
+    }
+
+    // -eの式を先に評価し､その後にファイルを指定順に評価する
+    let mut inputs = opts
+        .expressions
+        .into_iter()
+        .enumerate()
+        .map(|(i, expr)| Input::Expression(i + 1, expr))
+        .collect::<Vec<_>>();
+    for path in opts.formula_files {
+        if path.as_os_str() == "-" {
+            inputs.push(Input::Stdin);
+        } else {
+            inputs.push(Input::File(path));
+        }
+    }
+    if inputs.is_empty() {
+        inputs.push(Input::Stdin);
+    }
+
+    let config = Config::load(opts.config.as_deref())?.merge(Config {
+        mode: if opts.interval { Some(NumberMode::Interval) } else { opts.mode },
+        radix: opts.radix,
+        precision: opts.precision,
+        stack_policy: opts.stack_policy,
+        verbose: opts.verbose.then_some(true),
+        prelude: opts.prelude,
+        lang: opts.lang,
+        numbers: opts.numbers,
+    });
+    let format = Format {
+        radix: config.radix.unwrap_or(10),
+        precision: config.precision,
+        numbers: config.numbers.unwrap_or_default(),
+        lang: config.lang.unwrap_or_else(locale::Lang::from_env),
+    };
+    let limits = opts.limits.limits();
+    if opts.watch {
+        let path = match inputs.as_slice() {
+            [Input::File(path)] => path,
+            _ => bail!("--watch needs exactly one formula file"),
+        };
+        return watch::run(path, || build_calculator(&config, opts.seed, limits), &format);
+    }
+    if opts.jobs.is_some() || opts.stats {
+        let jobs = opts.jobs.unwrap_or(1);
+        if jobs == 0 {
+            bail!("--jobs must be at least 1");
+        }
+        if opts.jobs.is_some() && config.verbose == Some(true) {
+            bail!("--verbose cannot be combined with --jobs");
+        }
+        let calcurators = (0..jobs)
+            .map(|_| build_calculator(&config, opts.seed, limits))
+            .collect::<Result<Vec<_>>>()?;
+        let batch_opts = batch::Options {
+            label: opts.label,
+            format,
+            independent: opts.jobs.is_some(),
+            seed: opts.seed,
+        };
+        let scripts = inputs.iter().map(open_input);
+        let stats = batch::run(scripts, calcurators, &batch_opts, BufWriter::new(stdout()), stderr())?;
+        if opts.stats {
+            eprintln!("{}", stats);
+        }
+        if stats.failed > 0 {
+            process::exit(1);
+        }
+        return Ok(());
+    }
+    let calcurator = build_calculator(&config, opts.seed, limits)?;
+    let mut failed = false;
+    for input in &inputs {
+        // 読めない入力があっても残りの入力は評価し､最後に非0で終了する
+        if let Err(e) = run_input(input, &calcurator, opts.label, &format) {
+            eprintln!("Error: {:#}", e);
+            failed = true;
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+fn build_calculator(config: &Config, seed: Option<u64>, limits: Limits) -> Result<RpnCalculator> {
+    let mut calcurator = RpnCalculator::new(config.verbose.unwrap_or(false))
+        .with_limits(limits)
+        .with_mode(config.mode.unwrap_or(NumberMode::Auto))
+        .with_policy(config.stack_policy.unwrap_or(StackPolicy::Strict))
+        .with_numbers(config.numbers.unwrap_or_default());
+    if let Some(seed) = seed {
+        calcurator = calcurator.with_seed(seed);
     }
+    if let Some(prelude) = &config.prelude {
+        load_prelude(&calcurator, prelude)?;
+    }
+    Ok(calcurator)
 }
 
-// ※トレイト境界は､以下のように書いても同じ
-// fn run<R: BufRead>(reader: R, verbose: bool) -> Result<()> { /**/ }
-fn run<R>(reader: R, verbose: bool) -> Result<()>
-where
-    R: BufRead,
-{
-    let calcurator = RpnCalculator::new(verbose);
-    for line in reader.lines() {
+// preludeには変数の定義だけを書ける. 1行でも失敗したら起動しない
+fn load_prelude(calcurator: &RpnCalculator, path: &Path) -> Result<()> {
+    for line in Script::open(path)? {
         let line = line?;
-        match calcurator.eval(&line) {
-            Ok(answer) => println!("{}", answer),
-            Err(e) => println!("{:#?}", e),
+        let values = calcurator
+            .eval_line(&line.text)
+            .map_err(|e| anyhow!("{}:{}: {}", line.source, line.number, e))?;
+        if !values.is_empty() {
+            bail!("{}:{}: prelude lines must only define names", line.source, line.number);
         }
     }
     Ok(())
 }
 
-// cfgアトリビュートはコンディショナル的な属性. ここではcargo testの時のみ有効になる
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_ok() {
-        let calclulator = RpnCalculator::new(false);
-        assert_eq!(calclulator.eval("5").unwrap(), 5);
-        assert_eq!(calclulator.eval("50").unwrap(), 50);
-        assert_eq!(calclulator.eval("-50").unwrap(), -50);
-
-        assert_eq!(calclulator.eval("2 3 +").unwrap(), 5);
-        assert_eq!(calclulator.eval("2 3 -").unwrap(), -1);
-        assert_eq!(calclulator.eval("2 3 *").unwrap(), 6);
-        assert_eq!(calclulator.eval("2 3 /").unwrap(), 0);
-        assert_eq!(calclulator.eval("2 3 %").unwrap(), 2);
-    }
+fn open_input(input: &Input) -> Result<Script> {
+    let source = input.name();
+    Ok(match input {
+        Input::Expression(_, expr) => Script::new(Box::new(Cursor::new(expr.clone())), &source),
+        Input::Stdin => Script::new(Box::new(BufReader::new(stdin())), &source),
+        Input::File(path) => Script::open(path)?,
+    })
+}
 
-    #[test]
-    fn test_ng() {
-        let calclulator = RpnCalculator::new(false);
-        assert!(calclulator.eval("").is_err());
-        assert!(calclulator.eval("1 1 1 +").is_err());
-        assert!(calclulator.eval("+ 1 1").is_err());
-        
+fn run_input(input: &Input, calcurator: &RpnCalculator, label: bool, format: &Format) -> Result<()> {
+    run(open_input(input)?, calcurator, label, format)
+}
+
+// コメントや継続行､includeはScript側で処理済みなので､ここでは1行ずつ評価するだけ
+fn run(script: Script, calcurator: &RpnCalculator, label: bool, format: &Format) -> Result<()> {
+    for line in script {
+        let line = line?;
+        if label {
+            print!("{}:{}: ", line.source, line.number);
+        }
+        // "3 =x" のように値が残らない行は何も表示しない
+        match calcurator.eval_line(&line.text) {
+            Ok(values) if values.is_empty() => {
+                if label {
+                    println!();
+                }
+            }
+            Ok(values) => println!("{}", format.values(&values)),
+            Err(e) => {
+                println!("{}", format.error(&e));
+                // エラーになったトークンを^で示す. 標準出力は1行1結果のままにしたいので標準エラーに出す
+                if let Some((col, len)) = e.pos().and_then(|pos| lexer::span(&line.text, pos, format.numbers)) {
+                    eprintln!("  {}\n  {}{}", line.text, " ".repeat(col - 1), "^".repeat(len));
+                }
+            }
+        }
     }
+    Ok(())
 }
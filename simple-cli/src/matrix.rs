@@ -0,0 +1,250 @@
+// 小さな密行列. リストのリスト [[1 2] [3 4]] を行列として扱う
+#[derive(Clone, Debug, PartialEq)]
+pub struct Matrix {
+    rows: usize,
+    cols: usize,
+    data: Vec<f64>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MatrixError {
+    Shape(String),
+    Singular,
+}
+
+// 特異行列とみなすピボットの大きさ
+const EPSILON: f64 = 1e-12;
+
+impl Matrix {
+    pub fn from_rows(rows: Vec<Vec<f64>>) -> Result<Self, MatrixError> {
+        let cols = rows.first().map_or(0, Vec::len);
+        if rows.is_empty() || cols == 0 {
+            return Err(MatrixError::Shape("matrix must not be empty".to_string()));
+        }
+        if rows.iter().any(|row| row.len() != cols) {
+            return Err(MatrixError::Shape("rows have different lengths".to_string()));
+        }
+        Ok(Matrix {
+            rows: rows.len(),
+            cols,
+            data: rows.concat(),
+        })
+    }
+
+    // ベクトルは1列の行列として扱う
+    pub fn column(values: Vec<f64>) -> Self {
+        Matrix {
+            rows: values.len(),
+            cols: 1,
+            data: values,
+        }
+    }
+
+    pub fn to_rows(&self) -> Vec<Vec<f64>> {
+        self.data.chunks(self.cols).map(<[f64]>::to_vec).collect()
+    }
+
+    pub fn to_column(&self) -> Vec<f64> {
+        self.data.clone()
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn shape(&self) -> String {
+        format!("{}x{}", self.rows, self.cols)
+    }
+
+    fn get(&self, r: usize, c: usize) -> f64 {
+        self.data[r * self.cols + c]
+    }
+
+    pub fn transpose(&self) -> Matrix {
+        let mut data = Vec::with_capacity(self.data.len());
+        for c in 0..self.cols {
+            for r in 0..self.rows {
+                data.push(self.get(r, c));
+            }
+        }
+        Matrix {
+            rows: self.cols,
+            cols: self.rows,
+            data,
+        }
+    }
+
+    pub fn mul(&self, rhs: &Matrix) -> Result<Matrix, MatrixError> {
+        if self.cols != rhs.rows {
+            return Err(MatrixError::Shape(format!(
+                "cannot multiply {} by {}",
+                self.shape(),
+                rhs.shape()
+            )));
+        }
+        let mut data = vec![0.0; self.rows * rhs.cols];
+        for r in 0..self.rows {
+            for c in 0..rhs.cols {
+                data[r * rhs.cols + c] = (0..self.cols).map(|k| self.get(r, k) * rhs.get(k, c)).sum();
+            }
+        }
+        Ok(Matrix {
+            rows: self.rows,
+            cols: rhs.cols,
+            data,
+        })
+    }
+
+    fn require_square(&self, op: &str) -> Result<(), MatrixError> {
+        if self.rows != self.cols {
+            return Err(MatrixError::Shape(format!(
+                "{} needs a square matrix but got {}",
+                op,
+                self.shape()
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn det(&self) -> Result<f64, MatrixError> {
+        self.require_square("det")?;
+        match self.eliminate(&Matrix::column(vec![0.0; self.rows])) {
+            Ok((_, det)) => Ok(det),
+            Err(MatrixError::Singular) => Ok(0.0),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn inv(&self) -> Result<Matrix, MatrixError> {
+        self.require_square("inv")?;
+        let mut identity = vec![0.0; self.rows * self.rows];
+        for i in 0..self.rows {
+            identity[i * self.rows + i] = 1.0;
+        }
+        let identity = Matrix {
+            rows: self.rows,
+            cols: self.rows,
+            data: identity,
+        };
+        self.eliminate(&identity).map(|(x, _)| x)
+    }
+
+    // Ax = b を解く
+    pub fn solve(&self, b: &Matrix) -> Result<Matrix, MatrixError> {
+        self.require_square("solve")?;
+        if b.rows != self.rows {
+            return Err(MatrixError::Shape(format!(
+                "cannot solve {} system with {} right-hand side",
+                self.shape(),
+                b.shape()
+            )));
+        }
+        self.eliminate(b).map(|(x, _)| x)
+    }
+
+    // 部分ピボット選択付きのガウス・ジョルダン法. 解と行列式を返す
+    fn eliminate(&self, b: &Matrix) -> Result<(Matrix, f64), MatrixError> {
+        let n = self.rows;
+        let mut a = self.to_rows();
+        let mut x = b.to_rows();
+        let mut det = 1.0;
+        for col in 0..n {
+            let pivot = (col..n)
+                .max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))
+                .unwrap();
+            if a[pivot][col].abs() < EPSILON {
+                return Err(MatrixError::Singular);
+            }
+            if pivot != col {
+                a.swap(pivot, col);
+                x.swap(pivot, col);
+                det = -det;
+            }
+            let p = a[col][col];
+            det *= p;
+            a[col].iter_mut().for_each(|v| *v /= p);
+            x[col].iter_mut().for_each(|v| *v /= p);
+            let (pivot_a, pivot_x) = (a[col].clone(), x[col].clone());
+            for row in (0..n).filter(|&row| row != col) {
+                let factor = a[row][col];
+                for (v, p) in a[row].iter_mut().zip(&pivot_a) {
+                    *v -= factor * p;
+                }
+                for (v, p) in x[row].iter_mut().zip(&pivot_x) {
+                    *v -= factor * p;
+                }
+            }
+        }
+        Ok((Matrix::from_rows(x)?, det))
+    }
+}
+
+pub fn dot(a: &[f64], b: &[f64]) -> Result<f64, MatrixError> {
+    if a.len() != b.len() {
+        return Err(MatrixError::Shape(format!(
+            "cannot take dot product of length {} and {}",
+            a.len(),
+            b.len()
+        )));
+    }
+    Ok(a.iter().zip(b).map(|(x, y)| x * y).sum())
+}
+
+pub fn cross(a: &[f64], b: &[f64]) -> Result<Vec<f64>, MatrixError> {
+    if a.len() != 3 || b.len() != 3 {
+        return Err(MatrixError::Shape(format!(
+            "cross product needs two 3-vectors but got length {} and {}",
+            a.len(),
+            b.len()
+        )));
+    }
+    Ok(vec![
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn m(rows: &[&[f64]]) -> Matrix {
+        Matrix::from_rows(rows.iter().map(|r| r.to_vec()).collect()).unwrap()
+    }
+
+    #[test]
+    fn test_mul_transpose() {
+        let a = m(&[&[1.0, 2.0], &[3.0, 4.0]]);
+        let b = m(&[&[5.0], &[6.0]]);
+        assert_eq!(a.mul(&b).unwrap(), m(&[&[17.0], &[39.0]]));
+        assert_eq!(a.transpose(), m(&[&[1.0, 3.0], &[2.0, 4.0]]));
+        assert!(matches!(b.mul(&b), Err(MatrixError::Shape(_))));
+    }
+
+    #[test]
+    fn test_det_inv_solve() {
+        let a = m(&[&[4.0, 7.0], &[2.0, 6.0]]);
+        assert!((a.det().unwrap() - 10.0).abs() < 1e-9);
+        let inv = a.inv().unwrap();
+        let id = a.mul(&inv).unwrap();
+        assert!((id.get(0, 0) - 1.0).abs() < 1e-9 && id.get(0, 1).abs() < 1e-9);
+        let x = a.solve(&Matrix::column(vec![1.0, 2.0])).unwrap();
+        assert!((x.get(0, 0) + 0.8).abs() < 1e-9 && (x.get(1, 0) - 0.6).abs() < 1e-9);
+        let singular = m(&[&[1.0, 2.0], &[2.0, 4.0]]);
+        assert_eq!(singular.det().unwrap(), 0.0);
+        assert_eq!(singular.inv(), Err(MatrixError::Singular));
+    }
+
+    #[test]
+    fn test_vector() {
+        assert_eq!(dot(&[1.0, 2.0, 3.0], &[4.0, 5.0, 6.0]).unwrap(), 32.0);
+        assert_eq!(cross(&[1.0, 0.0, 0.0], &[0.0, 1.0, 0.0]).unwrap(), vec![0.0, 0.0, 1.0]);
+        assert!(dot(&[1.0], &[1.0, 2.0]).is_err());
+        assert!(cross(&[1.0, 2.0], &[1.0, 2.0]).is_err());
+    }
+}
@@ -0,0 +1,145 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+// スタックに積む数値. 整数同士の計算は整数のまま行い､
+// 小数が混ざった場合や平均などの統計値は浮動小数点数になる
+#[derive(Clone, Copy, PartialEq)]
+pub enum Number {
+    Int(i64),
+    Float(f64),
+}
+
+impl Number {
+    pub fn parse(token: &str) -> Option<Self> {
+        if let Ok(x) = token.parse::<i64>() {
+            return Some(Number::Int(x));
+        }
+        // f64のparseは"inf"や"NaN"も受け付けてしまうので､数字を含むものだけにする
+        let digits = token.trim_start_matches(['+', '-']).trim_start_matches('.');
+        if !digits.starts_with(|c: char| c.is_ascii_digit()) {
+            return None;
+        }
+        token.parse::<f64>().ok().map(Number::Float)
+    }
+
+    pub fn to_f64(self) -> f64 {
+        match self {
+            Number::Int(x) => x as f64,
+            Number::Float(x) => x,
+        }
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.to_f64() == 0.0
+    }
+
+    // 整数同士ならchecked_*で計算し､オーバーフローした場合はNoneを返す
+    fn apply(
+        self,
+        rhs: Self,
+        int: fn(i64, i64) -> Option<i64>,
+        float: fn(f64, f64) -> f64,
+    ) -> Option<Self> {
+        match (self, rhs) {
+            (Number::Int(x), Number::Int(y)) => int(x, y).map(Number::Int),
+            (x, y) => Some(Number::Float(float(x.to_f64(), y.to_f64()))),
+        }
+    }
+
+    pub fn checked_add(self, rhs: Self) -> Option<Self> {
+        self.apply(rhs, i64::checked_add, |x, y| x + y)
+    }
+
+    pub fn checked_sub(self, rhs: Self) -> Option<Self> {
+        self.apply(rhs, i64::checked_sub, |x, y| x - y)
+    }
+
+    pub fn checked_mul(self, rhs: Self) -> Option<Self> {
+        self.apply(rhs, i64::checked_mul, |x, y| x * y)
+    }
+
+    // 0除算のチェックは呼び出し側で行う
+    pub fn checked_div(self, rhs: Self) -> Option<Self> {
+        self.apply(rhs, i64::checked_div, |x, y| x / y)
+    }
+
+    pub fn checked_rem(self, rhs: Self) -> Option<Self> {
+        self.apply(rhs, i64::checked_rem, |x, y| x % y)
+    }
+
+    pub fn total_cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Number::Int(x), Number::Int(y)) => x.cmp(y),
+            (x, y) => x.to_f64().total_cmp(&y.to_f64()),
+        }
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Number::Int(x) => write!(f, "{}", x),
+            Number::Float(x) => write!(f, "{}", x),
+        }
+    }
+}
+
+// verbose表示でInt(1)のように出ると見づらいので､Displayと同じ表示にする
+impl fmt::Debug for Number {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl From<i64> for Number {
+    fn from(x: i64) -> Self {
+        Number::Int(x)
+    }
+}
+
+impl From<f64> for Number {
+    fn from(x: f64) -> Self {
+        Number::Float(x)
+    }
+}
+
+// テストで assert_eq!(calc.eval("5").unwrap(), 5) と書けるようにする
+impl PartialEq<i64> for Number {
+    fn eq(&self, other: &i64) -> bool {
+        match self {
+            Number::Int(x) => x == other,
+            Number::Float(x) => *x == *other as f64,
+        }
+    }
+}
+
+impl PartialEq<f64> for Number {
+    fn eq(&self, other: &f64) -> bool {
+        self.to_f64() == *other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Number::parse("-50"), Some(Number::Int(-50)));
+        assert_eq!(Number::parse("2.5"), Some(Number::Float(2.5)));
+        assert_eq!(Number::parse(".5"), Some(Number::Float(0.5)));
+        assert_eq!(Number::parse("1e3"), Some(Number::Float(1000.0)));
+        assert_eq!(Number::parse("inf"), None);
+        assert_eq!(Number::parse("NaN"), None);
+        assert_eq!(Number::parse("+"), None);
+    }
+
+    #[test]
+    fn test_promotion() {
+        let x = Number::Int(7);
+        assert_eq!(x.checked_div(Number::Int(2)), Some(Number::Int(3)));
+        assert_eq!(x.checked_div(Number::Float(2.0)), Some(Number::Float(3.5)));
+        assert_eq!(Number::Int(i64::MAX).checked_add(Number::Int(1)), None);
+        assert_eq!(Number::Float(3.0).to_string(), "3");
+    }
+}
@@ -0,0 +1,249 @@
+// 数値を1つ書いたファイル(assets/number.txt)を読み､2倍にして返す
+// 同じ処理をエラーの扱い方を変えて3通りに書いてある. src/bin/err_*.rs と err_number から使う
+//
+//   manual     エラーの列挙型とDisplay､Fromを手で書く
+//   thiserror  同じような列挙型をthiserrorで書く
+//   anyhow     エラーの型は作らず､anyhow::Errorに文脈を付けて返す
+//
+// 検査の内容は3つとも同じ. 前後の空白を除いた文字列が
+//   10文字以上なら大きすぎる､先頭が1でなければエラー､i32として読めなければエラー
+// ※ 空のファイルは先頭が1でないエラーになる
+// ※ readerから読む場合のnameはエラーメッセージに出す名前("-" なら標準入力など)
+//
+// どのエラーも元になったエラー(I/OやParseIntError)をsource()で返す. 表示はreport.rsでそろえる
+
+const MAX_LEN: usize = 10;
+
+pub mod manual {
+    use super::MAX_LEN;
+    use std::error::Error;
+    use std::fmt;
+    use std::fs::File;
+    use std::io::{self, Read};
+    use std::path::Path;
+
+    #[derive(Debug)]
+    pub enum MyError {
+        Io(io::Error),
+        TooLarge(usize),
+        FirstDigit,
+        Num(std::num::ParseIntError),
+    }
+    // 原因はsource()で返すので､Displayには含めない(含めると原因を表示した時に2回出てしまう)
+    impl fmt::Display for MyError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                MyError::Io(_) => write!(f, "I/O Error"),
+                MyError::TooLarge(len) => write!(f, "Validation Error:it may be too large number ({} digits)", len),
+                MyError::FirstDigit => write!(f, "Validation Error:first digit is not 1"),
+                MyError::Num(_) => write!(f, "Parse Error"),
+            }
+        }
+    }
+    // Debugを実装していればErrorはsource()だけ書けばよい
+    impl Error for MyError {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+            match self {
+                MyError::Io(cause) => Some(cause),
+                MyError::Num(cause) => Some(cause),
+                MyError::TooLarge(_) | MyError::FirstDigit => None,
+            }
+        }
+    }
+    impl From<io::Error> for MyError {
+        fn from(cause: io::Error) -> Self {
+            Self::Io(cause)
+        }
+    }
+    impl From<std::num::ParseIntError> for MyError {
+        fn from(cause: std::num::ParseIntError) -> Self {
+            Self::Num(cause)
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Result<i32, MyError> {
+        from_reader(File::open(path)?)
+    }
+
+    // 手書きの列挙型には名前を入れる場所が無いので､nameは受け取らない
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<i32, MyError> {
+        let mut num_str = String::new();
+        reader.read_to_string(&mut num_str)?;
+        let num_str = num_str.trim();
+
+        if num_str.len() >= MAX_LEN {
+            return Err(MyError::TooLarge(num_str.len()));
+        }
+        if !num_str.starts_with('1') {
+            return Err(MyError::FirstDigit);
+        }
+        num_str
+            .parse::<i32>()
+            // mapはOk(T)の場合のみ処理される
+            .map(|t| t * 2)
+            // map_errorはErr(E)の場合のみ処理される
+            .map_err(MyError::from)
+    }
+}
+
+pub mod with_thiserror {
+    use super::MAX_LEN;
+    use std::fs::File;
+    use std::io::{self, Read};
+    use std::path::Path;
+    use thiserror::Error;
+
+    // thiserrorのError型を継承する
+    #[derive(Error, Debug)]
+    pub enum MyError {
+        // errorアトリビュートで､fmt::Displayした際の表示を決めることができる
+        // sourceアトリビュート(またはsourceという名前のフィールド)がsource()で返される
+        #[error("failed to read string from {name}")]
+        ReadError {
+            name: String,
+            #[source]
+            source: io::Error,
+        },
+        #[error("it may be too large number ({0} digits)")]
+        TooLarge(usize),
+        #[error("first digit is not 1")]
+        FirstDigit,
+        // #[error(transparent)] にすると発生元のエラーの表示とsource()をそのまま使うが､
+        // 原因として1段深く表示したいので自分のメッセージを付ける
+        #[error("failed to parse string")]
+        // fromアトリビュートをつけておくと､fromで受けられる(sourceにもなる)
+        ParseError(#[from] std::num::ParseIntError),
+    }
+
+    pub fn from_path(path: &Path) -> Result<i32, MyError> {
+        let name = path.display().to_string();
+        let file = File::open(path).map_err(|source| MyError::ReadError {
+            name: name.clone(),
+            source,
+        })?;
+        from_reader(file, &name)
+    }
+
+    pub fn from_reader<R: Read>(mut reader: R, name: &str) -> Result<i32, MyError> {
+        let mut num_str = String::new();
+        reader
+            .read_to_string(&mut num_str)
+            .map_err(|source| MyError::ReadError {
+                name: name.into(),
+                source,
+            })?;
+        let num_str = num_str.trim();
+
+        if num_str.len() >= MAX_LEN {
+            return Err(MyError::TooLarge(num_str.len()));
+        }
+        if !num_str.starts_with('1') {
+            return Err(MyError::FirstDigit);
+        }
+        // ?演算子でもfromで受けられる
+        Ok(num_str.parse::<i32>().map(|t| t * 2)?)
+    }
+}
+
+pub mod with_anyhow {
+    use super::MAX_LEN;
+    use anyhow::{bail, ensure, Context, Result};
+    use std::fs::File;
+    use std::io::Read;
+    use std::path::Path;
+
+    pub fn from_path(path: &Path) -> Result<i32> {
+        let name = path.display().to_string();
+        let file = File::open(path).with_context(|| format!("failed to read string from {}", name))?;
+        from_reader(file, &name)
+    }
+
+    pub fn from_reader<R: Read>(mut reader: R, name: &str) -> Result<i32> {
+        let mut num_str = String::new();
+        reader
+            .read_to_string(&mut num_str)
+            .with_context(|| format!("failed to read string from {}", name))?;
+        let num_str = num_str.trim();
+
+        // bailやensureを使うと､簡単に早期returnが実現できる
+        if num_str.len() >= MAX_LEN {
+            bail!("it may be too large number ({} digits)", num_str.len());
+        }
+        ensure!(num_str.starts_with('1'), "first digit is not 1");
+
+        // contextやwith_contextは､エラーの場合のみ処理される
+        // (with_contextは変数キャプチャしたい時に使うこと)
+        num_str
+            .parse::<i32>()
+            .map(|t| t * 2)
+            .context("failed to parse string")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+    use std::path::Path;
+
+    // assets/number/ にエラーになるファイルを置いてある. missing.txt は存在しない
+    const CASES: [&str; 6] = ["ok", "missing", "empty", "alpha", "long", "two"];
+
+    fn path(case: &str) -> std::path::PathBuf {
+        Path::new("assets/number").join(format!("{}.txt", case))
+    }
+
+    #[test]
+    fn test_manual() {
+        let results = CASES.map(|case| manual::from_path(&path(case)));
+        assert_eq!(results[0].as_ref().unwrap(), &246);
+        assert!(matches!(results[1], Err(manual::MyError::Io(_))));
+        assert!(matches!(results[2], Err(manual::MyError::FirstDigit)));
+        assert!(matches!(results[3], Err(manual::MyError::Num(_))));
+        assert!(matches!(results[4], Err(manual::MyError::TooLarge(12))));
+        assert!(matches!(results[5], Err(manual::MyError::FirstDigit)));
+        assert_eq!(results[5].as_ref().unwrap_err().to_string(), "Validation Error:first digit is not 1");
+        let e = results[3].as_ref().unwrap_err();
+        assert_eq!(e.source().unwrap().to_string(), "invalid digit found in string");
+    }
+
+    #[test]
+    fn test_thiserror() {
+        let results = CASES.map(|case| with_thiserror::from_path(&path(case)));
+        assert_eq!(results[0].as_ref().unwrap(), &246);
+        let messages = results[1..].iter().map(|r| r.as_ref().unwrap_err().to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            [
+                "failed to read string from assets/number/missing.txt",
+                "first digit is not 1",
+                "failed to parse string",
+                "it may be too large number (12 digits)",
+                "first digit is not 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_anyhow() {
+        let results = CASES.map(|case| with_anyhow::from_path(&path(case)));
+        assert_eq!(results[0].as_ref().unwrap(), &246);
+        let messages = results[1..].iter().map(|r| format!("{:#}", r.as_ref().unwrap_err())).collect::<Vec<_>>();
+        assert!(messages[0].starts_with("failed to read string from assets/number/missing.txt: "));
+        assert_eq!(messages[2], "failed to parse string: invalid digit found in string");
+        assert_eq!(messages[3], "it may be too large number (12 digits)");
+    }
+
+    #[test]
+    fn test_reader() {
+        let input = || std::io::Cursor::new("  150\n");
+        assert_eq!(manual::from_reader(input()).unwrap(), 300);
+        assert_eq!(with_thiserror::from_reader(input(), "-").unwrap(), 300);
+        assert_eq!(with_anyhow::from_reader(input(), "-").unwrap(), 300);
+        // UTF-8として読めない入力は読み込みのエラー
+        let broken = || std::io::Cursor::new(vec![0xff, 0xfe]);
+        assert!(matches!(manual::from_reader(broken()), Err(manual::MyError::Io(_))));
+        let e = with_anyhow::from_reader(broken(), "-").unwrap_err();
+        assert_eq!(e.to_string(), "failed to read string from -");
+    }
+}
@@ -0,0 +1,135 @@
+use crate::report::Report;
+use std::any::Any;
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::cell::{Cell, RefCell};
+use std::error::Error;
+use std::fmt;
+use std::panic::{self, PanicHookInfo, UnwindSafe};
+use std::thread;
+
+// パニックをエラー(Report)に変えて返す境界. 長く動かすサーバで1つの要求のパニックで全体を止めないために使う
+//
+//   panic_boundary::install_hook();
+//   let res: Result<i32, Report> = panic_boundary::catch(|| ...);
+//
+// フックはパニックした場所､スレッド名､(RUST_BACKTRACEを設定していれば)バックトレースを記録する
+// catchの中のパニックはエラーとして返すので表示せず､外のパニックは標準のフックと同じように表示する
+//
+// ※ panic = "abort" でビルドした場合(cargo run --profile release-abort)はcatch_unwindで捕まえられず､
+//    フックが呼ばれた直後にプロセスが終わる. その場合は記録を返せないので､catchの中でもフックで表示する
+#[derive(Debug)]
+pub struct Panicked {
+    pub message: String,
+    // "src/bin/err_panic.rs:20:13". フックを入れていなければNone
+    pub location: Option<String>,
+    pub thread: String,
+    backtrace: Option<String>,
+}
+
+impl fmt::Display for Panicked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "thread '{}' panicked", self.thread)?;
+        if let Some(location) = &self.location {
+            write!(f, " at {}", location)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl Error for Panicked {}
+
+thread_local! {
+    // catchの入れ子の深さと､フックが記録した直前のパニック
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+    static LAST: RefCell<Option<Panicked>> = const { RefCell::new(None) };
+}
+
+pub fn install_hook() {
+    panic::set_hook(Box::new(|info| {
+        let panicked = record(info);
+        let inside = DEPTH.with(Cell::get) > 0;
+        if !inside || cfg!(panic = "abort") {
+            eprintln!("{}", panicked);
+            if let Some(backtrace) = &panicked.backtrace {
+                eprintln!("stack backtrace:\n{}", backtrace.trim_end());
+            }
+        }
+        if inside {
+            LAST.with(|last| last.replace(Some(panicked)));
+        }
+    }));
+}
+
+fn record(info: &PanicHookInfo) -> Panicked {
+    let backtrace = Backtrace::capture();
+    Panicked {
+        message: payload_message(info.payload()),
+        location: info.location().map(|l| l.to_string()),
+        thread: thread::current().name().unwrap_or("<unnamed>").to_string(),
+        backtrace: (backtrace.status() == BacktraceStatus::Captured).then(|| backtrace.to_string()),
+    }
+}
+
+// panic!("...") のメッセージは&strかStringで渡される
+fn payload_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "Box<dyn Any>".to_string()
+    }
+}
+
+pub fn catch<T, F>(f: F) -> Result<T, Report>
+where
+    F: FnOnce() -> T + UnwindSafe,
+{
+    DEPTH.with(|depth| depth.set(depth.get() + 1));
+    let res = panic::catch_unwind(f);
+    DEPTH.with(|depth| depth.set(depth.get() - 1));
+    res.map_err(|payload| {
+        // フックを入れていなければ場所はわからない
+        let panicked = LAST.with(RefCell::take).unwrap_or_else(|| Panicked {
+            message: payload_message(payload.as_ref()),
+            location: None,
+            thread: thread::current().name().unwrap_or("<unnamed>").to_string(),
+            backtrace: None,
+        });
+        let backtrace = panicked.backtrace.clone();
+        Report::with_backtrace_text(&panicked, backtrace)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::Kind;
+
+    // フックはプロセス全体で1つなので､入れるテストは1つにまとめる
+    #[test]
+    fn test_catch() {
+        assert_eq!(catch(|| 1 + 1).unwrap(), 2);
+        install_hook();
+        let report = thread::Builder::new()
+            .name("worker".to_string())
+            .spawn(|| {
+                catch(|| {
+                    let digits: Vec<u32> = Vec::new();
+                    digits[0]
+                })
+            })
+            .unwrap()
+            .join()
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(report.kind(), Kind::Panic);
+        let line = report.line();
+        assert!(line.starts_with("thread 'worker' panicked at src/panic_boundary.rs:"), "{}", line);
+        assert!(line.ends_with(": index out of bounds: the len is 0 but the index is 0"), "{}", line);
+        // 入れ子にしても内側で止まる
+        let outer = catch(|| catch(|| panic!("inner {}", 1)).unwrap_err().message().to_string()).unwrap();
+        assert!(outer.ends_with(": inner 1"), "{}", outer);
+        let _ = panic::take_hook();
+    }
+}
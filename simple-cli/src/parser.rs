@@ -0,0 +1,151 @@
+use crate::date::Date;
+use crate::error::CalcError;
+use crate::lexer::{self, Tok, Token};
+use crate::locale::Numbers;
+use crate::number::Number;
+use std::fmt;
+
+// 構文木の要素
+#[derive(Clone, PartialEq)]
+pub enum Term {
+    Num(Number),
+    // 90min のように数値の直後に書いた単位
+    Quantity(Number, String),
+    Date(Date),
+    // 3.2+-0.1 (中央値と誤差)
+    Interval(Number, Number),
+    Word(String),
+    Str(String),
+    // [ ... ] リストのリテラル. 中身を評価した結果がリストの要素になる
+    List(Vec<Node>),
+    // { ... } クォーテーション. 評価せずにそのままスタックに積む
+    Quote(Vec<Node>),
+}
+
+// posはトークンの番号(1始まり)､colは桁(1始まり). エラー表示に使う
+#[derive(Clone, PartialEq)]
+pub struct Node {
+    pub term: Term,
+    pub pos: usize,
+    pub col: usize,
+}
+
+pub fn parse(formula: &str) -> Result<Vec<Node>, CalcError> {
+    parse_with(formula, Numbers::C)
+}
+
+pub fn parse_with(formula: &str, numbers: Numbers) -> Result<Vec<Node>, CalcError> {
+    parse_tokens(lexer::lex(formula, numbers)?)
+}
+
+// 字句解析の結果を先に調べたい場合(トークン数の上限など)に使う
+pub fn parse_tokens(tokens: Vec<Token>) -> Result<Vec<Node>, CalcError> {
+    parse_block(&mut tokens.into_iter(), None)
+}
+
+// closeは対応する閉じ括弧と､開き括弧の位置
+fn parse_block<I>(tokens: &mut I, close: Option<(char, usize)>) -> Result<Vec<Node>, CalcError>
+where
+    I: Iterator<Item = Token>,
+{
+    let mut nodes = Vec::new();
+    while let Some(Token { tok, pos, col, .. }) = tokens.next() {
+        let term = match tok {
+            Tok::Open('[') => Term::List(parse_block(tokens, Some((']', pos)))?),
+            Tok::Open(_) => Term::Quote(parse_block(tokens, Some(('}', pos)))?),
+            Tok::Close(c) => {
+                return match close {
+                    Some((expected, _)) if expected == c => Ok(nodes),
+                    _ => Err(CalcError::UnbalancedBracket { pos }),
+                }
+            }
+            Tok::Num(x) => Term::Num(x),
+            Tok::Quantity(x, unit) => Term::Quantity(x, unit),
+            Tok::Date(date) => Term::Date(date),
+            Tok::Interval(x, r) => Term::Interval(x, r),
+            Tok::Word(word) => Term::Word(word),
+            Tok::Str(s) => Term::Str(s),
+        };
+        nodes.push(Node { term, pos, col });
+    }
+    match close {
+        // 閉じ括弧が無いまま終わった
+        Some((_, open)) => Err(CalcError::UnbalancedBracket { pos: open }),
+        None => Ok(nodes),
+    }
+}
+
+fn write_block(f: &mut fmt::Formatter<'_>, nodes: &[Node]) -> fmt::Result {
+    for (i, node) in nodes.iter().enumerate() {
+        if i > 0 {
+            write!(f, " ")?;
+        }
+        write!(f, "{}", node)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.term {
+            Term::Num(x) => write!(f, "{}", x),
+            Term::Quantity(x, unit) => write!(f, "{}{}", x, unit),
+            Term::Date(date) => write!(f, "{}", date),
+            Term::Interval(x, r) => write!(f, "{}+-{}", x, r),
+            Term::Word(word) => write!(f, "{}", word),
+            Term::Str(s) => write!(f, "{:?}", s),
+            Term::List(nodes) => {
+                write!(f, "[")?;
+                write_block(f, nodes)?;
+                write!(f, "]")
+            }
+            Term::Quote(nodes) => write!(f, "{}", Block(nodes)),
+        }
+    }
+}
+
+// verbose表示では､以前のトークン列と同じく"..."で囲んで表示する
+impl fmt::Debug for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.to_string())
+    }
+}
+
+// クォーテーションの表示用
+pub struct Block<'a>(pub &'a [Node]);
+
+impl fmt::Display for Block<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{ ")?;
+        write_block(f, self.0)?;
+        write!(f, " }}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unspaced() {
+        let nodes = parse("[1 2 3]{2*}map").unwrap();
+        assert_eq!(nodes.iter().map(|n| n.to_string()).collect::<Vec<_>>(), vec!["[1 2 3]", "{ 2 * }", "map"]);
+        assert_eq!(nodes[2].col, 12);
+    }
+
+    #[test]
+    fn test_parse() {
+        let nodes = parse("[1 [2]] { 2 * } map").unwrap();
+        assert_eq!(nodes.len(), 3);
+        assert_eq!(nodes[0].to_string(), "[1 [2]]");
+        assert_eq!(nodes[1].to_string(), "{ 2 * }");
+        assert_eq!(nodes[2].pos, 11);
+    }
+
+    #[test]
+    fn test_unbalanced() {
+        assert_eq!(parse("[1 2"), Err(CalcError::UnbalancedBracket { pos: 1 }));
+        assert_eq!(parse("1 2 ]"), Err(CalcError::UnbalancedBracket { pos: 3 }));
+        assert_eq!(parse("{ 1 ]"), Err(CalcError::UnbalancedBracket { pos: 3 }));
+    }
+}
@@ -0,0 +1,255 @@
+use crate::calculator::RpnCalculator;
+use crate::number::Number;
+use crate::value::Value;
+use anyhow::{anyhow, bail, Result};
+use std::io::Write;
+
+// rpncalc plot の設定
+#[derive(Debug)]
+pub struct Options {
+    pub var: String,
+    pub from: f64,
+    pub to: f64,
+    pub samples: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+// 評価できなかった点(0除算など)はyをNoneにして､グラフ上では途切れさせる
+#[derive(Debug, PartialEq)]
+pub struct Point {
+    pub x: f64,
+    pub y: Option<f64>,
+}
+
+impl Options {
+    fn validate(&self) -> Result<()> {
+        if !(self.from.is_finite() && self.to.is_finite()) || self.from >= self.to {
+            bail!("--from and --to must be finite and --from must be less than --to");
+        }
+        if self.samples < 2 || self.width < 2 || self.height < 2 {
+            bail!("samples, width and height must be at least 2");
+        }
+        Ok(())
+    }
+}
+
+// fromからtoまで等間隔にsamples個の点を取り､変数を束縛して式を評価する
+pub fn sample(calculator: &RpnCalculator, formula: &str, opts: &Options) -> Result<Vec<Point>> {
+    opts.validate()?;
+    let step = (opts.to - opts.from) / (opts.samples - 1) as f64;
+    let mut points = Vec::with_capacity(opts.samples);
+    let mut first_error = None;
+    for i in 0..opts.samples {
+        let x = opts.from + step * i as f64;
+        calculator.set_var(&opts.var, Value::Num(Number::Float(x)));
+        let y = match calculator.eval(formula) {
+            Ok(Value::Num(y)) if y.to_f64().is_finite() => Some(y.to_f64()),
+            Ok(Value::Num(_)) => None,
+            Ok(other) => {
+                first_error.get_or_insert_with(|| anyhow!("formula must return a number but got {}", other.type_name()));
+                None
+            }
+            Err(e) => {
+                first_error.get_or_insert_with(|| anyhow!("{} = {}: {}", opts.var, x, e));
+                None
+            }
+        };
+        points.push(Point { x, y });
+    }
+    // 1点も評価できなければ式そのものが間違っているとみなす
+    match first_error {
+        Some(e) if points.iter().all(|p| p.y.is_none()) => Err(e),
+        _ => Ok(points),
+    }
+}
+
+// yの範囲. 定数関数のように幅が0の場合は上下に1ずつ広げる
+fn y_range(points: &[Point]) -> (f64, f64) {
+    let ys = points.iter().filter_map(|p| p.y);
+    let (lo, hi) = ys.fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), y| (lo.min(y), hi.max(y)));
+    if lo == hi {
+        (lo - 1.0, hi + 1.0)
+    } else {
+        (lo, hi)
+    }
+}
+
+fn label(v: f64) -> String {
+    let s = format!("{:.2}", v);
+    if s == "-0.00" {
+        "0.00".to_string()
+    } else {
+        s
+    }
+}
+
+// 端末向けのASCIIグラフ. 範囲内にあればx軸(y=0)とy軸(x=0)も描く
+pub fn render(points: &[Point], opts: &Options) -> String {
+    let (w, h) = (opts.width, opts.height);
+    let (ymin, ymax) = y_range(points);
+    let col = |x: f64| ((x - opts.from) / (opts.to - opts.from) * (w - 1) as f64).round() as usize;
+    let row = |y: f64| ((ymax - y) / (ymax - ymin) * (h - 1) as f64).round() as usize;
+
+    let mut grid = vec![vec![' '; w]; h];
+    if ymin <= 0.0 && 0.0 <= ymax {
+        grid[row(0.0)].iter_mut().for_each(|c| *c = '-');
+    }
+    if opts.from <= 0.0 && 0.0 <= opts.to {
+        let c = col(0.0);
+        for line in grid.iter_mut() {
+            line[c] = if line[c] == '-' { '+' } else { '|' };
+        }
+    }
+    for p in points {
+        if let Some(y) = p.y {
+            grid[row(y)][col(p.x)] = '*';
+        }
+    }
+
+    let (top, bottom) = (label(ymax), label(ymin));
+    let margin = top.len().max(bottom.len());
+    let mut out = String::new();
+    for (i, line) in grid.iter().enumerate() {
+        let y_label = match i {
+            0 => top.as_str(),
+            i if i == h - 1 => bottom.as_str(),
+            _ => "",
+        };
+        let line = line.iter().collect::<String>();
+        out += &format!("{:>margin$} |{}\n", y_label, line.trim_end(), margin = margin);
+    }
+    out += &format!("{:>margin$} +{}\n", "", "-".repeat(w), margin = margin);
+    let (left, right) = (label(opts.from), label(opts.to));
+    let gap = (w + 1).saturating_sub(left.len() + right.len()).max(1);
+    out += &format!("{:>margin$}  {}{}{}\n", "", left, " ".repeat(gap), right, margin = margin);
+    out
+}
+
+pub fn write_csv<W: Write>(points: &[Point], var: &str, out: &mut W) -> Result<()> {
+    writeln!(out, "{},y", var)?;
+    for p in points {
+        match p.y {
+            Some(y) => writeln!(out, "{},{}", p.x, y)?,
+            None => writeln!(out, "{},", p.x)?,
+        }
+    }
+    Ok(())
+}
+
+// SVGの大きさ(ピクセル)と余白
+const SVG_WIDTH: f64 = 640.0;
+const SVG_HEIGHT: f64 = 400.0;
+const SVG_MARGIN: f64 = 50.0;
+
+pub fn write_svg<W: Write>(points: &[Point], opts: &Options, out: &mut W) -> Result<()> {
+    let (ymin, ymax) = y_range(points);
+    let sx = |x: f64| SVG_MARGIN + (x - opts.from) / (opts.to - opts.from) * (SVG_WIDTH - 2.0 * SVG_MARGIN);
+    let sy = |y: f64| SVG_MARGIN + (ymax - y) / (ymax - ymin) * (SVG_HEIGHT - 2.0 * SVG_MARGIN);
+
+    writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+        SVG_WIDTH, SVG_HEIGHT, SVG_WIDTH, SVG_HEIGHT
+    )?;
+    writeln!(out, r#"<rect width="100%" height="100%" fill="white"/>"#)?;
+    // 枠と軸
+    writeln!(
+        out,
+        r#"<rect x="{m}" y="{m}" width="{}" height="{}" fill="none" stroke="gray"/>"#,
+        SVG_WIDTH - 2.0 * SVG_MARGIN,
+        SVG_HEIGHT - 2.0 * SVG_MARGIN,
+        m = SVG_MARGIN
+    )?;
+    if ymin <= 0.0 && 0.0 <= ymax {
+        let y = sy(0.0);
+        writeln!(out, r#"<line x1="{}" y1="{y}" x2="{}" y2="{y}" stroke="gray"/>"#, sx(opts.from), sx(opts.to), y = y)?;
+    }
+    if opts.from <= 0.0 && 0.0 <= opts.to {
+        let x = sx(0.0);
+        writeln!(out, r#"<line x1="{x}" y1="{}" x2="{x}" y2="{}" stroke="gray"/>"#, sy(ymax), sy(ymin), x = x)?;
+    }
+    // 評価できなかった点で線を区切る
+    for segment in points.split(|p| p.y.is_none()).filter(|s| !s.is_empty()) {
+        let coords = segment
+            .iter()
+            .map(|p| format!("{:.2},{:.2}", sx(p.x), sy(p.y.unwrap())))
+            .collect::<Vec<_>>();
+        writeln!(out, r#"<polyline points="{}" fill="none" stroke="steelblue"/>"#, coords.join(" "))?;
+    }
+    let labels = [
+        (SVG_MARGIN - 5.0, SVG_MARGIN, "end", label(ymax)),
+        (SVG_MARGIN - 5.0, SVG_HEIGHT - SVG_MARGIN, "end", label(ymin)),
+        (SVG_MARGIN, SVG_HEIGHT - SVG_MARGIN + 20.0, "middle", label(opts.from)),
+        (SVG_WIDTH - SVG_MARGIN, SVG_HEIGHT - SVG_MARGIN + 20.0, "middle", label(opts.to)),
+    ];
+    for (x, y, anchor, text) in labels {
+        writeln!(
+            out,
+            r#"<text x="{}" y="{}" text-anchor="{}" font-family="monospace" font-size="12">{}</text>"#,
+            x, y, anchor, text
+        )?;
+    }
+    writeln!(out, "</svg>")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(from: f64, to: f64, samples: usize) -> Options {
+        Options {
+            var: "x".to_string(),
+            from,
+            to,
+            samples,
+            width: 7,
+            height: 5,
+        }
+    }
+
+    #[test]
+    fn test_sample() {
+        let calculator = RpnCalculator::new(false);
+        let points = sample(&calculator, "x x *", &options(-1.0, 1.0, 3)).unwrap();
+        assert_eq!(
+            points,
+            vec![
+                Point { x: -1.0, y: Some(1.0) },
+                Point { x: 0.0, y: Some(0.0) },
+                Point { x: 1.0, y: Some(1.0) },
+            ]
+        );
+        // 0除算になる点だけ抜ける
+        let points = sample(&calculator, "1 x /", &options(-1.0, 1.0, 3)).unwrap();
+        assert_eq!(points[1].y, None);
+        assert!(sample(&calculator, "x y +", &options(-1.0, 1.0, 3)).is_err());
+        assert!(sample(&calculator, "x", &options(1.0, -1.0, 3)).is_err());
+    }
+
+    #[test]
+    fn test_render() {
+        let calculator = RpnCalculator::new(false);
+        let opts = options(-3.0, 3.0, 7);
+        let points = sample(&calculator, "x", &opts).unwrap();
+        let expected = [
+            " 3.00 |   |  *",
+            "      |   |**",
+            "      |---*---",
+            "      | **|",
+            "-3.00 |*  |",
+            "      +-------",
+            "       -3.00 3.00",
+        ];
+        assert_eq!(render(&points, &opts), expected.join("\n") + "\n");
+    }
+
+    #[test]
+    fn test_csv() {
+        let points = vec![Point { x: 0.0, y: Some(1.5) }, Point { x: 1.0, y: None }];
+        let mut out = Vec::new();
+        write_csv(&points, "t", &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "t,y\n0,1.5\n1,\n");
+    }
+}
@@ -0,0 +1,214 @@
+use crate::panic_boundary::Panicked;
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::num::ParseIntError;
+
+// エラーをsource()の連鎖までたどって表示する. number_sourceの3通りの実装で同じ形になる
+//
+//   failed to read string from assets/number/missing.txt
+//
+//   Caused by:
+//       1: No such file or directory (os error 2)
+//
+//   Stack backtrace:
+//      0: ...
+//
+// バックトレースは RUST_BACKTRACE か RUST_LIB_BACKTRACE が設定されている場合だけ表示する
+// ※ anyhowはエラーを作った場所でバックトレースを取るが､手書きとthiserrorの列挙型は持っていない
+//    (thiserrorのバックトレースはnightlyでしか取り出せない)ので､Reportを作った場所で取る
+#[derive(Debug)]
+pub struct Report {
+    kind: Kind,
+    message: String,
+    causes: Vec<String>,
+    backtrace: Option<String>,
+}
+
+// エラーの種類. 終了コードはsysexits.hに合わせる(exit.rs)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    NotFound,
+    PermissionDenied,
+    // その他の入出力のエラー
+    Io,
+    Parse,
+    // 読めたが値がおかしい(bail!やensure!､TooLargeなど)
+    Validation,
+    // panic_boundary::catchで捕まえたパニック
+    Panic,
+}
+
+impl Kind {
+    // 連鎖の中にio::ErrorかParseIntErrorがあればその種類､どちらも無ければ検査のエラーとみなす
+    // ※ 3通りの実装のどれでも､原因になったエラーはsource()でたどれる
+    fn of(error: &(dyn Error + 'static)) -> Kind {
+        let mut next = Some(error);
+        while let Some(e) = next {
+            if e.is::<Panicked>() {
+                return Kind::Panic;
+            }
+            if let Some(e) = e.downcast_ref::<io::Error>() {
+                return match e.kind() {
+                    io::ErrorKind::NotFound => Kind::NotFound,
+                    io::ErrorKind::PermissionDenied => Kind::PermissionDenied,
+                    // UTF-8として読めない入力も読み込みのエラーになる
+                    _ => Kind::Io,
+                };
+            }
+            if e.is::<ParseIntError>() {
+                return Kind::Parse;
+            }
+            next = e.source();
+        }
+        Kind::Validation
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::NotFound => "not-found",
+            Kind::PermissionDenied => "permission-denied",
+            Kind::Io => "io",
+            Kind::Parse => "parse",
+            Kind::Validation => "validation",
+            Kind::Panic => "panic",
+        }
+    }
+}
+
+impl Report {
+    pub fn new(error: &(dyn Error + 'static)) -> Report {
+        Report::with_backtrace(error, &Backtrace::capture())
+    }
+
+    fn with_backtrace(error: &(dyn Error + 'static), backtrace: &Backtrace) -> Report {
+        let text = (backtrace.status() == BacktraceStatus::Captured).then(|| backtrace.to_string());
+        Report::with_backtrace_text(error, text)
+    }
+
+    // パニックのようにバックトレースを先に文字列にしてある場合
+    pub(crate) fn with_backtrace_text(error: &(dyn Error + 'static), backtrace: Option<String>) -> Report {
+        let mut causes = Vec::new();
+        let mut source = error.source();
+        while let Some(cause) = source {
+            causes.push(cause.to_string());
+            source = cause.source();
+        }
+        Report {
+            kind: Kind::of(error),
+            message: error.to_string(),
+            causes,
+            backtrace,
+        }
+    }
+
+    pub fn kind(&self) -> Kind {
+        self.kind
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    // "failed to parse string: invalid digit found in string" のように原因まで1行につなげる
+    pub fn line(&self) -> String {
+        std::iter::once(&self.message).chain(&self.causes).cloned().collect::<Vec<_>>().join(": ")
+    }
+
+    // 直接の原因から順に並べたもの
+    pub fn causes(&self) -> &[String] {
+        &self.causes
+    }
+}
+
+impl From<anyhow::Error> for Report {
+    fn from(error: anyhow::Error) -> Report {
+        Report::with_backtrace(error.as_ref(), error.backtrace())
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if !self.causes.is_empty() {
+            write!(f, "\n\nCaused by:")?;
+            for (i, cause) in self.causes.iter().enumerate() {
+                write!(f, "\n    {}: {}", i + 1, cause)?;
+            }
+        }
+        if let Some(backtrace) = &self.backtrace {
+            write!(f, "\n\nStack backtrace:\n{}", backtrace.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::number_source::{manual, with_anyhow, with_thiserror};
+    use std::path::Path;
+
+    #[test]
+    fn test_chain() {
+        let path = Path::new("assets/number/alpha.txt");
+        let reports = [
+            Report::new(&manual::from_path(path).unwrap_err()),
+            Report::new(&with_thiserror::from_path(path).unwrap_err()),
+            Report::from(with_anyhow::from_path(path).unwrap_err()),
+        ];
+        for report in &reports {
+            assert_eq!(report.causes(), ["invalid digit found in string"]);
+        }
+        assert_eq!(reports[0].message(), "Parse Error");
+        // 環境変数によってはバックトレースが付くので､先頭だけ比べる
+        assert!(reports[1]
+            .to_string()
+            .starts_with("failed to parse string\n\nCaused by:\n    1: invalid digit found in string"));
+
+        let missing = with_anyhow::from_path(Path::new("assets/number/missing.txt")).unwrap_err();
+        let report = Report::with_backtrace(missing.as_ref(), &Backtrace::disabled());
+        assert_eq!(
+            report.to_string(),
+            "failed to read string from assets/number/missing.txt\n\nCaused by:\n    1: No such file or directory (os error 2)"
+        );
+    }
+
+    #[test]
+    fn test_kind() {
+        let kind = |case: &str| {
+            let path = Path::new("assets/number").join(format!("{}.txt", case));
+            [
+                Report::new(&manual::from_path(&path).unwrap_err()).kind(),
+                Report::new(&with_thiserror::from_path(&path).unwrap_err()).kind(),
+                Report::from(with_anyhow::from_path(&path).unwrap_err()).kind(),
+            ]
+        };
+        assert_eq!(kind("missing"), [Kind::NotFound; 3]);
+        assert_eq!(kind("alpha"), [Kind::Parse; 3]);
+        assert_eq!(kind("long"), [Kind::Validation; 3]);
+        assert_eq!(kind("empty"), [Kind::Validation; 3]);
+
+        // rootで実行するとファイルの権限では拒否されないので､読み込みで失敗するreaderを使う
+        struct Denied;
+        impl io::Read for Denied {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::PermissionDenied.into())
+            }
+        }
+        assert_eq!(Report::new(&manual::from_reader(Denied).unwrap_err()).kind(), Kind::PermissionDenied);
+        let report = Report::from(with_anyhow::from_reader(Denied, "-").unwrap_err());
+        assert_eq!(report.kind(), Kind::PermissionDenied);
+        assert_eq!(report.line(), "failed to read string from -: permission denied");
+    }
+
+    #[test]
+    fn test_backtrace() {
+        let e = with_thiserror::from_path(Path::new("assets/number/long.txt")).unwrap_err();
+        let report = Report::with_backtrace(&e, &Backtrace::force_capture());
+        let text = report.to_string();
+        assert!(text.starts_with("it may be too large number (12 digits)\n\nStack backtrace:\n"), "{}", text);
+        assert!(!Report::with_backtrace(&e, &Backtrace::disabled()).to_string().contains("backtrace"));
+    }
+}
@@ -0,0 +1,302 @@
+use anyhow::{bail, ensure, Context, Result};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Lines};
+use std::path::{Path, PathBuf};
+
+// 前処理済みの1行分の計算式
+#[derive(Debug, PartialEq)]
+pub struct Line {
+    pub source: String,
+    pub number: usize,
+    pub text: String,
+    // 式の直前のコメント行と､式と同じ行のコメント("#"は除く)
+    pub comments: Vec<String>,
+}
+
+// include中のファイル1つ分の読み込み状態
+struct Frame {
+    source: String,
+    // 循環検出用の正規化済みパス(-eや標準入力の場合はNone)
+    path: Option<PathBuf>,
+    // includeの相対パスの基準となるディレクトリ
+    dir: PathBuf,
+    lines: Lines<Box<dyn BufRead>>,
+    number: usize,
+}
+
+// 計算式ファイルを前処理しながら1行ずつ返すイテレータ
+// - "#"以降はコメントとして読み飛ばす(""で囲まれた中は除く)
+// - 空行は読み飛ばす
+// - 行末が"\"の場合は次の行に継続する
+// - include "path" でファイルを読み込む(循環したらエラー)
+pub struct Script {
+    frames: Vec<Frame>,
+    // 次の式に付けるコメント. 空行やincludeを挟むと捨てる
+    pending: Vec<String>,
+}
+
+impl Script {
+    pub fn new(reader: Box<dyn BufRead>, source: &str) -> Self {
+        let frame = Frame {
+            source: source.to_string(),
+            path: None,
+            dir: PathBuf::from("."),
+            lines: reader.lines(),
+            number: 0,
+        };
+        Self {
+            frames: vec![frame],
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut script = Self {
+            frames: Vec::new(),
+            pending: Vec::new(),
+        };
+        script.push_file(path, &path.display().to_string())?;
+        Ok(script)
+    }
+
+    fn push_file(&mut self, path: &Path, source: &str) -> Result<()> {
+        let f = File::open(path).with_context(|| format!("failed to open {}", source))?;
+        let canonical = path
+            .canonicalize()
+            .with_context(|| format!("failed to open {}", source))?;
+        ensure!(
+            self.frames.iter().all(|f| f.path.as_ref() != Some(&canonical)),
+            "include cycle detected: {}",
+            self.include_chain(source)
+        );
+        let dir = canonical
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| PathBuf::from("."));
+        let reader: Box<dyn BufRead> = Box::new(BufReader::new(f));
+        self.frames.push(Frame {
+            source: source.to_string(),
+            path: Some(canonical),
+            dir,
+            lines: reader.lines(),
+            number: 0,
+        });
+        Ok(())
+    }
+
+    fn include_chain(&self, source: &str) -> String {
+        let mut chain = self
+            .frames
+            .iter()
+            .map(|f| f.source.as_str())
+            .collect::<Vec<_>>();
+        chain.push(source);
+        chain.join(" -> ")
+    }
+
+    // 現在のファイルから物理行を1行読む. EOFならNone
+    fn read_physical(&mut self) -> Option<Result<String>> {
+        let frame = self.frames.last_mut()?;
+        match frame.lines.next()? {
+            Ok(line) => {
+                frame.number += 1;
+                Some(Ok(line))
+            }
+            Err(e) => {
+                let err = Err(e).with_context(|| format!("failed to read {}", frame.source));
+                // 読めなくなったファイルはそれ以上読まない
+                self.frames.pop();
+                Some(err)
+            }
+        }
+    }
+
+    fn next_line(&mut self) -> Option<Result<Line>> {
+        loop {
+            let depth = self.frames.len();
+            let physical = match self.read_physical() {
+                Some(Ok(line)) => line,
+                Some(Err(e)) => return Some(Err(e)),
+                None if depth == 0 => return None,
+                None => {
+                    // includeしたファイルの終わりなので､呼び出し元に戻る
+                    self.frames.pop();
+                    continue;
+                }
+            };
+
+            let frame = self.frames.last()?;
+            let source = frame.source.clone();
+            let number = frame.number;
+
+            // 継続行をまとめて1行にする
+            let mut comments = Vec::new();
+            let (code, comment) = split_comment(&physical);
+            comments.extend(comment);
+            let mut text = code.trim_end().to_string();
+            while let Some(head) = text.strip_suffix('\\') {
+                text = head.trim_end().to_string();
+                match self.read_physical() {
+                    Some(Ok(next)) => {
+                        let (code, comment) = split_comment(&next);
+                        comments.extend(comment);
+                        text.push(' ');
+                        text.push_str(code.trim());
+                    }
+                    Some(Err(e)) => return Some(Err(e)),
+                    None => break,
+                }
+            }
+
+            let text = text.trim();
+            if text.is_empty() {
+                // コメントだけの行は次の式のコメントとして取っておく
+                if comments.is_empty() {
+                    self.pending.clear();
+                } else {
+                    self.pending.append(&mut comments);
+                }
+                continue;
+            }
+            if let Some(rest) = directive(text, "include") {
+                self.pending.clear();
+                if let Err(e) = self.include(rest, &source, number) {
+                    return Some(Err(e));
+                }
+                continue;
+            }
+            self.pending.append(&mut comments);
+            return Some(Ok(Line {
+                source,
+                number,
+                text: text.to_string(),
+                comments: std::mem::take(&mut self.pending),
+            }));
+        }
+    }
+
+    fn include(&mut self, arg: &str, source: &str, number: usize) -> Result<()> {
+        let name = parse_quoted(arg)
+            .with_context(|| format!("{}:{}: malformed include directive", source, number))?;
+        let dir = self.frames.last().map(|f| f.dir.clone()).unwrap_or_default();
+        let path = dir.join(name);
+        self.push_file(&path, name)
+            .with_context(|| format!("{}:{}: failed to include \"{}\"", source, number, name))
+    }
+}
+
+impl Iterator for Script {
+    type Item = Result<Line>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_line()
+    }
+}
+
+// 式の部分と"#"以降のコメントに分ける. ""で囲まれた中の"#"はコメント扱いしない
+fn split_comment(line: &str) -> (&str, Option<String>) {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return (&line[..i], Some(line[i + 1..].trim().to_string())),
+            _ => {}
+        }
+    }
+    (line, None)
+}
+
+// "name 引数"の形の行なら引数部分を返す
+fn directive<'a>(text: &'a str, name: &str) -> Option<&'a str> {
+    let rest = text.strip_prefix(name)?;
+    if rest.starts_with(char::is_whitespace) {
+        Some(rest.trim())
+    } else {
+        None
+    }
+}
+
+fn parse_quoted(arg: &str) -> Result<&str> {
+    match arg.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+        Some(inner) if !inner.is_empty() && !inner.contains('"') => Ok(inner),
+        _ => bail!("expected a quoted path but got {}", arg),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn texts(input: &str) -> Vec<(usize, String)> {
+        Script::new(Box::new(Cursor::new(input.to_string())), "test")
+            .map(|line| line.unwrap())
+            .map(|line| (line.number, line.text))
+            .collect()
+    }
+
+    fn texts_result(input: &str) -> Result<Vec<Line>> {
+        Script::new(Box::new(Cursor::new(input.to_string())), "test").collect()
+    }
+
+    #[test]
+    fn test_comment_and_blank() {
+        let lines = texts("# header\n\n1 2 + # three\n   \n3 4 *\n");
+        assert_eq!(lines, vec![(3, "1 2 +".into()), (5, "3 4 *".into())]);
+    }
+
+    #[test]
+    fn test_continuation() {
+        let lines = texts("1 2 + \\\n  3 4 + * # 21\n5\n");
+        assert_eq!(lines, vec![(1, "1 2 + 3 4 + *".into()), (3, "5".into())]);
+    }
+
+    #[test]
+    fn test_comments() {
+        let lines = texts_result("# first\n# expect: 3\n1 2 + # trailing\n\n# dropped\n\n4\n")
+            .unwrap()
+            .into_iter()
+            .map(|line| line.comments)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            vec![
+                vec!["first".to_string(), "expect: 3".into(), "trailing".into()],
+                vec![]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_include() {
+        let lines = Script::open(Path::new("assets/include/main.rpn"))
+            .unwrap()
+            .map(|line| line.unwrap())
+            .map(|line| (line.source, line.number, line.text))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            vec![
+                ("common.rpn".into(), 2, "1 1 +".into()),
+                ("assets/include/main.rpn".into(), 5, "1 2 + 3 4 + *".into()),
+                ("assets/include/main.rpn".into(), 8, "10 5 -".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_include_cycle() {
+        let result = Script::open(Path::new("assets/include/cycle_a.rpn"))
+            .unwrap()
+            .collect::<Result<Vec<_>>>();
+        let message = format!("{:#}", result.unwrap_err());
+        assert!(message.contains("include cycle detected"), "{}", message);
+    }
+
+    #[test]
+    fn test_include_missing() {
+        let result = texts_result("include \"no_such_file.rpn\"\n");
+        assert!(result.is_err());
+        assert!(texts_result("include no_quote.rpn\n").is_err());
+    }
+}
@@ -0,0 +1,367 @@
+use crate::calculator::{Limits, RpnCalculator};
+use crate::error::CalcError;
+use crate::number::Number;
+use crate::value::Value;
+use anyhow::Result;
+use rpncalc::json::json_string;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+// rpncalc serve の設定. 接続ごとに同じ設定で計算機を作る
+#[derive(Clone, Copy, Debug)]
+pub struct Options {
+    // 1行(改行を除く)の最大バイト数
+    pub max_request: usize,
+    pub limits: Limits,
+    pub seed: Option<u64>,
+    // 同時に受ける接続の数. 超えた接続にはエラーを1行返して切る
+    pub max_connections: usize,
+    // この時間1行も届かなければ接続を切る
+    pub read_timeout: Duration,
+}
+
+// 指定が無い上限に使う値. 信用できない入力を受けるので､1つの要求でプロセス全体を止められないよう全部の種類を制限する
+// スタックの値の数と大きさは､行をまたいで持ち越したスタックに対して数える
+// ※ デバッグビルドでは入れ子1段で50KBほどスタックを使う(STACK_SIZEに収まるよう深さを決める)
+pub const DEFAULT_LIMITS: Limits = Limits {
+    max_steps: Some(100_000),
+    max_stack: Some(10_000),
+    max_depth: Some(100),
+    max_tokens: Some(10_000),
+    max_digits: Some(1000),
+    max_memory: Some(16 << 20),
+    max_time: Some(Duration::from_secs(5)),
+};
+
+// 接続ごとのスレッドのスタック. 入れ子の深い式(Limitsのmax_depthまで)を評価できるよう､メインスレッドと同じだけ取る
+const STACK_SIZE: usize = 8 << 20;
+
+enum Request {
+    Line(String),
+    TooLarge(usize),
+}
+
+// 使っている接続の数. スレッドが終わったら(パニックした場合も)減らす
+struct Slot(Arc<AtomicUsize>);
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// 接続ごとにスレッドを立て､1行読むごとに1行のJSONを返す
+pub fn run(listener: TcpListener, opts: Options) -> Result<()> {
+    let connections = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("accept failed: {}", e);
+                continue;
+            }
+        };
+        if connections.fetch_add(1, Ordering::SeqCst) >= opts.max_connections {
+            connections.fetch_sub(1, Ordering::SeqCst);
+            let message = format!("too many connections (limit {})", opts.max_connections);
+            let _ = writeln!(
+                &stream,
+                r#"{{"ok":false,"error":{{"kind":"TooManyConnections","pos":null,"message":{}}}}}"#,
+                json_string(&message)
+            );
+            continue;
+        }
+        let slot = Slot(Arc::clone(&connections));
+        if let Err(e) = stream.set_read_timeout(Some(opts.read_timeout)) {
+            eprintln!("set_read_timeout failed: {}", e);
+            continue;
+        }
+        let spawned = thread::Builder::new().stack_size(STACK_SIZE).spawn(move || {
+            let peer = stream
+                .peer_addr()
+                .map(|addr| addr.to_string())
+                .unwrap_or_else(|_| "?".to_string());
+            let res = stream
+                .try_clone()
+                .and_then(|reader| handle(BufReader::new(reader), &stream, &opts));
+            if let Err(e) = res {
+                eprintln!("{}: {}", peer, e);
+            }
+            // 接続を閉じる前に枠を空ける(閉じたのを見てすぐ接続し直す相手を断らないように)
+            drop(slot);
+        });
+        if let Err(e) = spawned {
+            eprintln!("spawn failed: {}", e);
+        }
+    }
+    Ok(())
+}
+
+// スタックと変数は接続が切れるまで持ち越す
+pub fn handle<R: BufRead, W: Write>(mut reader: R, mut writer: W, opts: &Options) -> io::Result<()> {
+    let mut calculator = RpnCalculator::new(false).with_limits(opts.limits);
+    if let Some(seed) = opts.seed {
+        calculator = calculator.with_seed(seed);
+    }
+    let mut stack = Vec::new();
+    while let Some(request) = read_request(&mut reader, opts.max_request)? {
+        let reply = match request {
+            Request::Line(line) if line.trim().is_empty() => continue,
+            Request::Line(line) => match calculator.eval_on(line.trim(), &mut stack) {
+                Ok(()) => format!(
+                    r#"{{"ok":true,"result":{},"depth":{}}}"#,
+                    stack.last().map_or("null".to_string(), to_json),
+                    stack.len()
+                ),
+                Err(e) => error_json(&e),
+            },
+            Request::TooLarge(size) => format!(
+                r#"{{"ok":false,"error":{{"kind":"RequestTooLarge","pos":null,"message":{}}}}}"#,
+                json_string(&format!(
+                    "request of {} bytes exceeds the limit of {} bytes",
+                    size, opts.max_request
+                ))
+            ),
+        };
+        writeln!(writer, "{}", reply)?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+// 上限を超えた行は読み捨てて､次の改行から読み直す
+fn read_request<R: BufRead>(reader: &mut R, max: usize) -> io::Result<Option<Request>> {
+    let mut line = Vec::new();
+    let mut size = 0;
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            if size == 0 {
+                return Ok(None);
+            }
+            break;
+        }
+        let (len, done) = match buf.iter().position(|&b| b == b'\n') {
+            Some(i) => (i + 1, true),
+            None => (buf.len(), false),
+        };
+        let chunk = &buf[..len];
+        let chunk = chunk.strip_suffix(b"\n").unwrap_or(chunk);
+        size += chunk.len();
+        if size <= max {
+            line.extend_from_slice(chunk);
+        }
+        reader.consume(len);
+        if done {
+            break;
+        }
+    }
+    if size > max {
+        return Ok(Some(Request::TooLarge(size)));
+    }
+    Ok(Some(Request::Line(String::from_utf8_lossy(&line).into_owned())))
+}
+
+fn error_json(e: &CalcError) -> String {
+    format!(
+        r#"{{"ok":false,"error":{{"kind":"{}","pos":{},"message":{}}}}}"#,
+        e.kind(),
+        e.pos().map_or("null".to_string(), |pos| pos.to_string()),
+        json_string(&e.to_string())
+    )
+}
+
+// 数値はJSONの数値､リストは配列にする. 単位付きの値などは表示用の文字列にする
+fn to_json(value: &Value) -> String {
+    match value {
+        Value::Num(Number::Int(x)) => x.to_string(),
+        Value::Num(Number::Float(x)) if x.is_finite() => x.to_string(),
+        Value::List(values) => format!("[{}]", values.iter().map(to_json).collect::<Vec<_>>().join(",")),
+        Value::Str(s) => json_string(s),
+        other => json_string(&other.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufReader, Cursor};
+    use std::net::TcpStream;
+
+    fn options() -> Options {
+        Options {
+            max_request: 32,
+            limits: Limits {
+                max_steps: Some(1000),
+                ..Limits::default()
+            },
+            seed: Some(0),
+            max_connections: 8,
+            read_timeout: Duration::from_secs(10),
+        }
+    }
+
+    fn session(input: &str) -> Vec<String> {
+        session_with(input, &options())
+    }
+
+    fn session_with(input: &str, opts: &Options) -> Vec<String> {
+        let mut out = Vec::new();
+        handle(Cursor::new(input.to_string()), &mut out, opts).unwrap();
+        String::from_utf8(out).unwrap().lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_session() {
+        let replies = session("1 2\n+\n\n3 =x\nx 0 /\n[1 2.5 \"a\"]\n[1 2.5] 2 m\nclear\n");
+        assert_eq!(
+            replies,
+            vec![
+                r#"{"ok":true,"result":2,"depth":2}"#,
+                r#"{"ok":true,"result":3,"depth":1}"#,
+                r#"{"ok":true,"result":3,"depth":1}"#,
+                r#"{"ok":false,"error":{"kind":"DivisionByZero","pos":3,"message":"division by zero at 3"}}"#,
+                r#"{"ok":true,"result":[1,2.5,"a"],"depth":2}"#,
+                r#"{"ok":true,"result":"2 m","depth":4}"#,
+                r#"{"ok":true,"result":null,"depth":0}"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_limits() {
+        let long = "1 ".repeat(20);
+        let replies = session(&format!("{}\n1 1000 range {{ 1 + }} map sum\n2\n", long));
+        assert!(replies[0].contains(r#""kind":"RequestTooLarge""#), "{}", replies[0]);
+        assert!(replies[1].contains(r#""kind":"StepLimitExceeded""#), "{}", replies[1]);
+        assert_eq!(replies[2], r#"{"ok":true,"result":2,"depth":1}"#);
+    }
+
+    // 1つの要求でプロセスを止められないこと. スタックの上限と大きさは持ち越したスタックに対して数える
+    #[test]
+    fn test_default_limits() {
+        let opts = Options {
+            max_request: 1_000_000,
+            limits: Limits::default().or(DEFAULT_LIMITS),
+            ..options()
+        };
+        let ones = "1 ".repeat(6000);
+        let lines = [
+            "[".repeat(300_000),
+            "[".repeat(5000),
+            "0 100000000 range len".to_string(),
+            "0 200000 range".to_string(),
+            "0 200000 range".to_string(),
+            "clear".to_string(),
+            ones.clone(),
+            ones,
+        ];
+        let replies = session_with(&(lines.join("\n") + "\n"), &opts);
+        let kinds = [
+            "TokenLimitExceeded",
+            "DepthLimitExceeded",
+            "MemoryLimitExceeded",
+            "",
+            "MemoryLimitExceeded",
+            "",
+            "",
+            "StackLimitExceeded",
+        ];
+        for (reply, kind) in replies.iter().zip(kinds) {
+            if kind.is_empty() {
+                assert!(reply.starts_with(r#"{"ok":true"#), "{}", reply);
+            } else {
+                assert!(reply.contains(&format!(r#""kind":"{}""#, kind)), "{}", reply);
+            }
+        }
+        assert_eq!(replies.len(), kinds.len());
+    }
+
+    #[test]
+    fn test_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || run(listener, options()));
+        let mut a = TcpStream::connect(addr).unwrap();
+        let mut b = TcpStream::connect(addr).unwrap();
+        let mut a_reader = BufReader::new(a.try_clone().unwrap());
+        let mut b_reader = BufReader::new(b.try_clone().unwrap());
+        let mut reply = String::new();
+        // 接続ごとに別のスタックを持つ
+        writeln!(a, "1 2").unwrap();
+        a_reader.read_line(&mut reply).unwrap();
+        writeln!(b, "10").unwrap();
+        b_reader.read_line(&mut reply).unwrap();
+        writeln!(a, "+").unwrap();
+        reply.clear();
+        a_reader.read_line(&mut reply).unwrap();
+        assert_eq!(reply.trim(), r#"{"ok":true,"result":3,"depth":1}"#);
+    }
+
+    // 大きな幅の書式で文字列を作らせても､その接続にエラーを返すだけでサーバは動き続ける
+    #[test]
+    fn test_tcp_fmt() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let opts = Options {
+            max_request: 4096,
+            limits: Limits::default().or(DEFAULT_LIMITS),
+            ..options()
+        };
+        thread::spawn(move || run(listener, opts));
+        let request = |conn: &mut TcpStream, line: &str| {
+            writeln!(conn, "{}", line).unwrap();
+            let mut reply = String::new();
+            BufReader::new(conn.try_clone().unwrap()).read_line(&mut reply).unwrap();
+            reply
+        };
+        let mut a = TcpStream::connect(addr).unwrap();
+        let reply = request(&mut a, r#"1 "%100000000000d" fmt"#);
+        assert!(reply.contains(r#""kind":"DigitLimitExceeded""#), "{}", reply);
+        assert_eq!(request(&mut a, "1 1 +").trim(), r#"{"ok":true,"result":2,"depth":1}"#);
+        // 桁数の上限を外してもメモリの上限で断る
+        let no_digits = Options {
+            limits: Limits {
+                max_digits: None,
+                ..opts.limits
+            },
+            ..opts
+        };
+        let replies = session_with("1 \"%100000000000d\" fmt\n", &no_digits);
+        assert!(replies[0].contains(r#""kind":"MemoryLimitExceeded""#), "{}", replies[0]);
+        let mut b = TcpStream::connect(addr).unwrap();
+        assert_eq!(request(&mut b, "2 3 *").trim(), r#"{"ok":true,"result":6,"depth":1}"#);
+    }
+
+    // 接続数の上限を超えた接続は断り､何も送らない接続は時間が来たら切る
+    #[test]
+    fn test_connections() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let opts = Options {
+            max_connections: 1,
+            read_timeout: Duration::from_millis(300),
+            ..options()
+        };
+        thread::spawn(move || run(listener, opts));
+        let read_line = |conn: &TcpStream| {
+            let mut reply = String::new();
+            BufReader::new(conn).read_line(&mut reply).unwrap();
+            reply
+        };
+        let mut a = TcpStream::connect(addr).unwrap();
+        writeln!(a, "1").unwrap();
+        assert_eq!(read_line(&a).trim(), r#"{"ok":true,"result":1,"depth":1}"#);
+        let b = TcpStream::connect(addr).unwrap();
+        assert!(read_line(&b).contains(r#""kind":"TooManyConnections""#));
+        // aは時間切れで切られ､空いた枠で次の接続を受ける
+        assert_eq!(read_line(&a), "");
+        let mut c = TcpStream::connect(addr).unwrap();
+        writeln!(c, "2").unwrap();
+        assert_eq!(read_line(&c).trim(), r#"{"ok":true,"result":2,"depth":1}"#);
+    }
+}
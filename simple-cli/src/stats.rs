@@ -0,0 +1,162 @@
+use crate::error::CalcError;
+use crate::number::Number;
+
+// スタック全体を集計する演算子
+// 先頭に"n"を付けると､スタックの先頭N個だけを集計する(例: 1 2 3 4 2 nsum => 1 2 7)
+pub const AGGREGATES: [&str; 10] = [
+    "sum", "mean", "median", "stdev", "var", "min", "max", "count", "prod", "linreg",
+];
+
+pub fn is_aggregate(name: &str) -> bool {
+    AGGREGATES.contains(&name)
+}
+
+// 集計結果はスタックに積む順に返す(linregだけは傾きと切片の2つ)
+pub fn aggregate(name: &str, values: &[Number], pos: usize) -> Result<Vec<Number>, CalcError> {
+    let res = match name {
+        "sum" => fold(values, Number::Int(0), Number::checked_add, pos)?,
+        "prod" => fold(values, Number::Int(1), Number::checked_mul, pos)?,
+        "count" => Number::Int(values.len() as i64),
+        "mean" => Number::Float(mean(require(values, 1, pos)?)),
+        "median" => median(require(values, 1, pos)?),
+        "var" => Number::Float(variance(require(values, 2, pos)?)),
+        "stdev" => Number::Float(variance(require(values, 2, pos)?).sqrt()),
+        "min" => *require(values, 1, pos)?
+            .iter()
+            .min_by(|x, y| x.total_cmp(y))
+            .unwrap(),
+        "max" => *require(values, 1, pos)?
+            .iter()
+            .max_by(|x, y| x.total_cmp(y))
+            .unwrap(),
+        "linreg" => return linreg(values, pos),
+        _ => unreachable!("unknown aggregate {}", name),
+    };
+    Ok(vec![res])
+}
+
+fn require(values: &[Number], n: usize, pos: usize) -> Result<&[Number], CalcError> {
+    if values.len() < n {
+        return Err(CalcError::StackUnderflow { pos });
+    }
+    Ok(values)
+}
+
+fn fold(
+    values: &[Number],
+    init: Number,
+    f: fn(Number, Number) -> Option<Number>,
+    pos: usize,
+) -> Result<Number, CalcError> {
+    values.iter().try_fold(init, |acc, &x| f(acc, x).ok_or(CalcError::Overflow { pos }))
+}
+
+fn mean(values: &[Number]) -> f64 {
+    values.iter().map(|x| x.to_f64()).sum::<f64>() / values.len() as f64
+}
+
+fn median(values: &[Number]) -> Number {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|x, y| x.total_cmp(y));
+    let mid = sorted.len() / 2;
+    if sorted.len() % 2 == 1 {
+        sorted[mid]
+    } else {
+        Number::Float((sorted[mid - 1].to_f64() + sorted[mid].to_f64()) / 2.0)
+    }
+}
+
+// p%点(0 <= p <= 100). 間の値は線形補間する
+pub fn percentile(values: &[Number], p: f64) -> f64 {
+    let mut sorted = values.iter().map(|x| x.to_f64()).collect::<Vec<_>>();
+    sorted.sort_by(f64::total_cmp);
+    let rank = p / 100.0 * (sorted.len() - 1) as f64;
+    let (lo, hi) = (rank.floor() as usize, rank.ceil() as usize);
+    sorted[lo] + (sorted[hi] - sorted[lo]) * (rank - lo as f64)
+}
+
+// 不偏分散(n-1で割る)
+fn variance(values: &[Number]) -> f64 {
+    let m = mean(values);
+    let sq = values.iter().map(|x| (x.to_f64() - m).powi(2)).sum::<f64>();
+    sq / (values.len() - 1) as f64
+}
+
+// x1 y1 x2 y2 ... の組から最小二乗法で傾きと切片を求める
+fn linreg(values: &[Number], pos: usize) -> Result<Vec<Number>, CalcError> {
+    if !values.len().is_multiple_of(2) {
+        return Err(CalcError::InvalidArgument {
+            pos,
+            reason: "linreg needs x y pairs".to_string(),
+        });
+    }
+    require(values, 4, pos)?;
+    let xs = values.iter().step_by(2).map(|x| x.to_f64()).collect::<Vec<_>>();
+    let ys = values.iter().skip(1).step_by(2).map(|y| y.to_f64()).collect::<Vec<_>>();
+    let n = xs.len() as f64;
+    let mx = xs.iter().sum::<f64>() / n;
+    let my = ys.iter().sum::<f64>() / n;
+    let sxx = xs.iter().map(|x| (x - mx).powi(2)).sum::<f64>();
+    let sxy = xs.iter().zip(&ys).map(|(x, y)| (x - mx) * (y - my)).sum::<f64>();
+    if sxx == 0.0 {
+        return Err(CalcError::DivisionByZero { pos });
+    }
+    let slope = sxy / sxx;
+    Ok(vec![Number::Float(slope), Number::Float(my - slope * mx)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ints(values: &[i64]) -> Vec<Number> {
+        values.iter().map(|&x| Number::Int(x)).collect()
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let values = ints(&[2, 4, 4, 4, 5, 5, 7, 9]);
+        assert_eq!(aggregate("sum", &values, 1).unwrap(), vec![Number::Int(40)]);
+        assert_eq!(aggregate("mean", &values, 1).unwrap(), vec![Number::Float(5.0)]);
+        assert_eq!(aggregate("median", &values, 1).unwrap(), vec![Number::Float(4.5)]);
+        assert_eq!(aggregate("min", &values, 1).unwrap(), vec![Number::Int(2)]);
+        assert_eq!(aggregate("max", &values, 1).unwrap(), vec![Number::Int(9)]);
+        assert_eq!(aggregate("count", &values, 1).unwrap(), vec![Number::Int(8)]);
+        assert_eq!(aggregate("var", &values, 1).unwrap(), vec![Number::Float(32.0 / 7.0)]);
+        assert_eq!(aggregate("prod", &ints(&[]), 1).unwrap(), vec![Number::Int(1)]);
+    }
+
+    #[test]
+    fn test_linreg() {
+        // y = 2x + 1
+        let values = ints(&[0, 1, 1, 3, 2, 5]);
+        assert_eq!(
+            aggregate("linreg", &values, 1).unwrap(),
+            vec![Number::Float(2.0), Number::Float(1.0)]
+        );
+        assert!(aggregate("linreg", &ints(&[1, 2, 3]), 1).is_err());
+        assert_eq!(
+            aggregate("linreg", &ints(&[1, 2, 1, 3]), 1),
+            Err(CalcError::DivisionByZero { pos: 1 })
+        );
+    }
+
+    #[test]
+    fn test_percentile() {
+        let values = ints(&[1, 2, 3, 4, 5]);
+        assert_eq!(percentile(&values, 0.0), 1.0);
+        assert_eq!(percentile(&values, 50.0), 3.0);
+        assert_eq!(percentile(&values, 95.0), 4.8);
+        assert_eq!(percentile(&values, 100.0), 5.0);
+    }
+
+    #[test]
+    fn test_empty() {
+        assert_eq!(aggregate("sum", &[], 3).unwrap(), vec![Number::Int(0)]);
+        assert_eq!(
+            aggregate("mean", &[], 3),
+            Err(CalcError::StackUnderflow { pos: 3 })
+        );
+        assert!(aggregate("stdev", &ints(&[1]), 3).is_err());
+    }
+}
@@ -0,0 +1,247 @@
+use crate::number::Number;
+
+// 文字列の演算子の下請け. スタック効果(-- の左が取り出す値､右が積む値)
+//   concat ( a b -- ab )
+//   len    ( s -- n )           文字数(リストなら要素数)
+//   upper  ( s -- S )   lower ( S -- s )
+//   substr ( s start n -- t )   start文字目(0始まり)からn文字
+//   fmt    ( x spec -- s )      specはprintf形式("%.2f" "USD %,d" など)か小数点以下の桁数
+//   parse  ( s -- x )
+//
+// fmtで使える変換は %d %f %e %E %x %X %o %b %s と %%. フラグは - + 0 ,(3桁区切り)
+// ※ 1つの書式で変換できる値は1つだけ
+
+type Result<T> = std::result::Result<T, String>;
+
+// 位置と長さは文字単位. 長さが余る場合は末尾までにする
+pub fn substr(s: &str, start: i64, len: i64) -> Result<String> {
+    let count = s.chars().count();
+    if start < 0 || len < 0 {
+        return Err(format!("start and length must be non-negative but got {} and {}", start, len));
+    }
+    if start as usize > count {
+        return Err(format!("start {} is out of range for a string of {} characters", start, count));
+    }
+    Ok(s.chars().skip(start as usize).take(len as usize).collect())
+}
+
+pub fn parse(s: &str) -> Result<Number> {
+    Number::parse(s.trim()).ok_or_else(|| format!("cannot parse {:?} as a number", s))
+}
+
+pub fn printf(format: &str, x: Number) -> Result<String> {
+    let mut out = String::new();
+    let mut converted = false;
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        if chars.peek() == Some(&'%') {
+            chars.next();
+            out.push('%');
+            continue;
+        }
+        if converted {
+            return Err(format!("format {:?} has more than one conversion", format));
+        }
+        let spec = Spec::parse(&mut chars)?;
+        out += &spec.apply(x)?;
+        converted = true;
+    }
+    if !converted {
+        return Err(format!("format {:?} has no conversion", format));
+    }
+    Ok(out)
+}
+
+// 書式で指定した幅と精度のうち最大のもの. 結果を作る前に大きさを制限するのに使う
+// ※ 書式の誤りはここでは無視する(printfでエラーになる)
+pub fn requested_len(format: &str) -> usize {
+    let mut len = 0;
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            continue;
+        }
+        if chars.peek() == Some(&'%') {
+            chars.next();
+            continue;
+        }
+        if let Ok(spec) = Spec::parse(&mut chars) {
+            len = len.max(spec.width).max(spec.precision.unwrap_or(0));
+        }
+    }
+    len
+}
+
+#[derive(Debug, Default)]
+struct Spec {
+    left: bool,
+    plus: bool,
+    zero: bool,
+    group: bool,
+    width: usize,
+    precision: Option<usize>,
+    conversion: char,
+}
+
+impl Spec {
+    // %の直後から変換文字までを読む
+    fn parse(chars: &mut impl Iterator<Item = char>) -> Result<Spec> {
+        let mut spec = Spec::default();
+        let mut c = chars.next();
+        while let Some(flag @ ('-' | '+' | '0' | ',')) = c {
+            match flag {
+                '-' => spec.left = true,
+                '+' => spec.plus = true,
+                '0' => spec.zero = true,
+                _ => spec.group = true,
+            }
+            c = chars.next();
+        }
+        while let Some(d) = c.and_then(|c| c.to_digit(10)) {
+            spec.width = spec.width.saturating_mul(10).saturating_add(d as usize);
+            c = chars.next();
+        }
+        if c == Some('.') {
+            let mut precision: usize = 0;
+            c = chars.next();
+            while let Some(d) = c.and_then(|c| c.to_digit(10)) {
+                precision = precision.saturating_mul(10).saturating_add(d as usize);
+                c = chars.next();
+            }
+            spec.precision = Some(precision);
+        }
+        spec.conversion = match c {
+            Some(c @ ('d' | 'f' | 'e' | 'E' | 'x' | 'X' | 'o' | 'b' | 's')) => c,
+            Some(c) => return Err(format!("unknown conversion `%{}`", c)),
+            None => return Err("incomplete conversion at the end of the format".to_string()),
+        };
+        Ok(spec)
+    }
+
+    fn apply(&self, x: Number) -> Result<String> {
+        let (negative, body) = match self.conversion {
+            'd' | 'x' | 'X' | 'o' | 'b' => {
+                let n = self.int(x)?;
+                let abs = n.unsigned_abs();
+                let body = match self.conversion {
+                    'd' if self.group => group(&abs.to_string()),
+                    'd' => abs.to_string(),
+                    'x' => format!("{:x}", abs),
+                    'X' => format!("{:X}", abs),
+                    'o' => format!("{:o}", abs),
+                    _ => format!("{:b}", abs),
+                };
+                (n < 0, body)
+            }
+            'f' => {
+                let x = x.to_f64();
+                let body = format!("{:.*}", self.precision.unwrap_or(6), x.abs());
+                (x < 0.0, if self.group { group(&body) } else { body })
+            }
+            'e' | 'E' => {
+                let x = x.to_f64();
+                let body = exponent(x.abs(), self.precision.unwrap_or(6));
+                let body = if self.conversion == 'E' { body.to_uppercase() } else { body };
+                (x < 0.0, body)
+            }
+            _ => {
+                let text = x.to_string();
+                match text.strip_prefix('-') {
+                    Some(rest) => (true, rest.to_string()),
+                    None => (false, text),
+                }
+            }
+        };
+        let sign = match (negative, self.plus) {
+            (true, _) => "-",
+            (false, true) => "+",
+            _ => "",
+        };
+        let fill = self.width.saturating_sub(sign.len() + body.chars().count());
+        Ok(if self.left {
+            format!("{}{}{}", sign, body, " ".repeat(fill))
+        } else if self.zero {
+            format!("{}{}{}", sign, "0".repeat(fill), body)
+        } else {
+            format!("{}{}{}", " ".repeat(fill), sign, body)
+        })
+    }
+
+    fn int(&self, x: Number) -> Result<i64> {
+        match x {
+            Number::Int(x) => Ok(x),
+            x => Err(format!("%{} needs an integer but got {}", self.conversion, x)),
+        }
+    }
+}
+
+// 整数部分を3桁ごとにカンマで区切る
+fn group(digits: &str) -> String {
+    let (int, frac) = digits.split_at(digits.find('.').unwrap_or(digits.len()));
+    let mut out = String::new();
+    for (i, c) in int.chars().enumerate() {
+        if i > 0 && (int.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out + frac
+}
+
+// Rustの{:e}は 1.5e3 なので､Cと同じ 1.500000e+03 の形にする
+fn exponent(x: f64, precision: usize) -> String {
+    let text = format!("{:.*e}", precision, x);
+    match text.split_once('e') {
+        Some((mantissa, exp)) => match exp.parse::<i32>() {
+            Ok(exp) => format!("{}e{}{:02}", mantissa, if exp < 0 { '-' } else { '+' }, exp.abs()),
+            Err(_) => text,
+        },
+        // infやNaN
+        None => text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_printf() {
+        assert_eq!(printf("%.2f", Number::Float(12.5)).unwrap(), "12.50");
+        assert_eq!(printf("USD %,.2f", Number::Float(1234567.891)).unwrap(), "USD 1,234,567.89");
+        assert_eq!(
+            printf("%d%d", Number::Int(1)).unwrap_err(),
+            "format \"%d%d\" has more than one conversion"
+        );
+        assert_eq!(printf("[%6d]", Number::Int(-42)).unwrap(), "[   -42]");
+        assert_eq!(printf("[%-6d]", Number::Int(42)).unwrap(), "[42    ]");
+        assert_eq!(printf("[%+06d]", Number::Int(42)).unwrap(), "[+00042]");
+        assert_eq!(printf("%x %%", Number::Int(255)).unwrap(), "ff %");
+        assert_eq!(printf("%08b", Number::Int(5)).unwrap(), "00000101");
+        assert_eq!(printf("%.3e", Number::Float(1234.5)).unwrap(), "1.234e+03");
+        assert_eq!(printf("%E", Number::Float(-0.00025)).unwrap(), "-2.500000E-04");
+        assert_eq!(printf("%s", Number::Float(2.5)).unwrap(), "2.5");
+        assert!(printf("%d", Number::Float(2.5)).is_err());
+        assert!(printf("%q", Number::Int(1)).is_err());
+        assert!(printf("%.2", Number::Int(1)).is_err());
+        assert!(printf("total", Number::Int(1)).is_err());
+        assert_eq!(requested_len("%8.3f and 100%%"), 8);
+        assert_eq!(requested_len("%.99999999999999999999999f"), usize::MAX);
+    }
+
+    #[test]
+    fn test_substr_parse() {
+        assert_eq!(substr("héllo", 1, 3).unwrap(), "éll");
+        assert_eq!(substr("abc", 1, 10).unwrap(), "bc");
+        assert_eq!(substr("abc", 3, 1).unwrap(), "");
+        assert!(substr("abc", 4, 1).is_err());
+        assert!(substr("abc", -1, 1).is_err());
+        assert_eq!(parse(" 42 ").unwrap(), Number::Int(42));
+        assert_eq!(parse("2.5").unwrap(), Number::Float(2.5));
+        assert!(parse("abc").is_err());
+    }
+}
@@ -0,0 +1,362 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+// 次元. SI基本単位(m, kg, s, A, K, mol, cd)それぞれの指数
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Dim([i8; 7]);
+
+impl Dim {
+    const NONE: Dim = Dim([0; 7]);
+
+    // 指数がi8に収まらなくなったらNone(m を130回掛けた場合など)
+    fn mul(self, rhs: Dim) -> Option<Dim> {
+        let mut dim = self.0;
+        for (d, r) in dim.iter_mut().zip(rhs.0) {
+            *d = d.checked_add(r)?;
+        }
+        Some(Dim(dim))
+    }
+
+    fn inv(self) -> Option<Dim> {
+        let mut dim = self.0;
+        for d in dim.iter_mut() {
+            *d = d.checked_neg()?;
+        }
+        Some(Dim(dim))
+    }
+}
+
+// 単位表の1行. prefixableならk(キロ)やm(ミリ)などの接頭辞を付けられる
+struct Entry {
+    symbol: &'static str,
+    factor: f64,
+    dim: [i8; 7],
+    prefixable: bool,
+}
+
+const fn entry(symbol: &'static str, factor: f64, dim: [i8; 7], prefixable: bool) -> Entry {
+    Entry {
+        symbol,
+        factor,
+        dim,
+        prefixable,
+    }
+}
+
+//                                  m  kg   s   A   K mol  cd
+const TABLE: [Entry; 24] = [
+    // SI基本単位(kgは接頭辞k + gとして扱う)
+    entry("m", 1.0, [1, 0, 0, 0, 0, 0, 0], true),
+    entry("g", 1e-3, [0, 1, 0, 0, 0, 0, 0], true),
+    entry("s", 1.0, [0, 0, 1, 0, 0, 0, 0], true),
+    entry("A", 1.0, [0, 0, 0, 1, 0, 0, 0], true),
+    entry("K", 1.0, [0, 0, 0, 0, 1, 0, 0], true),
+    entry("mol", 1.0, [0, 0, 0, 0, 0, 1, 0], true),
+    entry("cd", 1.0, [0, 0, 0, 0, 0, 0, 1], true),
+    // SI組立単位
+    entry("Hz", 1.0, [0, 0, -1, 0, 0, 0, 0], true),
+    entry("N", 1.0, [1, 1, -2, 0, 0, 0, 0], true),
+    entry("Pa", 1.0, [-1, 1, -2, 0, 0, 0, 0], true),
+    entry("J", 1.0, [2, 1, -2, 0, 0, 0, 0], true),
+    entry("W", 1.0, [2, 1, -3, 0, 0, 0, 0], true),
+    entry("C", 1.0, [0, 0, 1, 1, 0, 0, 0], true),
+    entry("V", 1.0, [2, 1, -3, -1, 0, 0, 0], true),
+    entry("ohm", 1.0, [2, 1, -3, -2, 0, 0, 0], true),
+    entry("F", 1.0, [-2, -1, 4, 2, 0, 0, 0], true),
+    entry("Wb", 1.0, [2, 1, -2, -1, 0, 0, 0], true),
+    entry("T", 1.0, [0, 1, -2, -1, 0, 0, 0], true),
+    entry("H", 1.0, [2, 1, -2, -2, 0, 0, 0], true),
+    // SI併用単位
+    entry("L", 1e-3, [3, 0, 0, 0, 0, 0, 0], true),
+    entry("min", 60.0, [0, 0, 1, 0, 0, 0, 0], false),
+    entry("h", 3600.0, [0, 0, 1, 0, 0, 0, 0], false),
+    entry("d", 86400.0, [0, 0, 1, 0, 0, 0, 0], false),
+    entry("w", 604_800.0, [0, 0, 1, 0, 0, 0, 0], false),
+];
+
+// 単位の別名. minは組み込みの演算子と同じ名前で変換先に書けないので minute と書けるようにする
+// ※ 表示は元の記号にする
+const ALIASES: [(&str, &str); 4] = [("minute", "min"), ("hour", "h"), ("day", "d"), ("week", "w")];
+
+const PREFIXES: [(&str, f64); 20] = [
+    ("Y", 1e24),
+    ("Z", 1e21),
+    ("E", 1e18),
+    ("P", 1e15),
+    ("T", 1e12),
+    ("G", 1e9),
+    ("M", 1e6),
+    ("k", 1e3),
+    ("h", 1e2),
+    ("da", 1e1),
+    ("d", 1e-1),
+    ("c", 1e-2),
+    ("m", 1e-3),
+    ("u", 1e-6),
+    ("µ", 1e-6),
+    ("n", 1e-9),
+    ("p", 1e-12),
+    ("f", 1e-15),
+    ("a", 1e-18),
+    ("z", 1e-21),
+];
+
+// 単位. 表示用に"km"や"s"などの記号と指数の組も持っておく
+#[derive(Clone, PartialEq, Debug)]
+pub struct Unit {
+    // SI基本単位に換算する時の係数
+    factor: f64,
+    dim: Dim,
+    terms: Vec<(String, i32)>,
+}
+
+impl Unit {
+    fn scalar() -> Self {
+        Unit {
+            factor: 1.0,
+            dim: Dim::NONE,
+            terms: Vec::new(),
+        }
+    }
+
+    // 単位表に完全一致するものを優先し､無ければ接頭辞付きとして探す
+    // (例: "m"はミリではなくメートル､"mm"はミリメートル)
+    pub fn lookup(symbol: &str) -> Option<Self> {
+        let symbol = ALIASES.iter().find(|(alias, _)| *alias == symbol).map_or(symbol, |(_, s)| s);
+        let found = TABLE
+            .iter()
+            .find(|e| e.symbol == symbol)
+            .map(|e| (e.factor, e.dim))
+            .or_else(|| {
+                PREFIXES.iter().find_map(|(prefix, scale)| {
+                    let base = symbol.strip_prefix(prefix)?;
+                    TABLE
+                        .iter()
+                        .find(|e| e.prefixable && e.symbol == base)
+                        .map(|e| (e.factor * scale, e.dim))
+                })
+            });
+        found.map(|(factor, dim)| Unit {
+            factor,
+            dim: Dim(dim),
+            terms: vec![(symbol.to_string(), 1)],
+        })
+    }
+
+    pub fn is_dimensionless(&self) -> bool {
+        self.dim == Dim::NONE
+    }
+
+    pub fn mul(&self, rhs: &Unit) -> Result<Unit, UnitError> {
+        let dim = self.dim.mul(rhs.dim).ok_or(UnitError::Overflow)?;
+        let mut terms = self.terms.clone();
+        for (symbol, exp) in &rhs.terms {
+            match terms.iter_mut().find(|(s, _)| s == symbol) {
+                Some((_, e)) => *e = e.checked_add(*exp).ok_or(UnitError::Overflow)?,
+                None => terms.push((symbol.clone(), *exp)),
+            }
+        }
+        terms.retain(|(_, e)| *e != 0);
+        Ok(Unit {
+            factor: self.factor * rhs.factor,
+            dim,
+            terms,
+        })
+    }
+
+    pub fn div(&self, rhs: &Unit) -> Result<Unit, UnitError> {
+        self.mul(&rhs.inv()?)
+    }
+
+    fn inv(&self) -> Result<Unit, UnitError> {
+        Ok(Unit {
+            factor: 1.0 / self.factor,
+            dim: self.dim.inv().ok_or(UnitError::Overflow)?,
+            terms: self.terms.iter().map(|(s, e)| (s.clone(), -e)).collect(),
+        })
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join = |terms: Vec<(&String, i32)>| {
+            terms
+                .iter()
+                .map(|(s, e)| if *e == 1 { s.to_string() } else { format!("{}^{}", s, e) })
+                .collect::<Vec<_>>()
+                .join("*")
+        };
+        let num = self.terms.iter().filter(|(_, e)| *e > 0).map(|(s, e)| (s, *e)).collect::<Vec<_>>();
+        let den = self.terms.iter().filter(|(_, e)| *e < 0).map(|(s, e)| (s, -e)).collect::<Vec<_>>();
+        let num = if num.is_empty() { "1".to_string() } else { join(num) };
+        match den.len() {
+            0 => write!(f, "{}", num),
+            1 => write!(f, "{}/{}", num, join(den)),
+            _ => write!(f, "{}/({})", num, join(den)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum UnitError {
+    DimensionMismatch(String, String),
+    DivisionByZero,
+    // 次元の指数が大きくなりすぎた
+    Overflow,
+}
+
+// 単位付きの値. valueはunitで表した値(SI基本単位に換算した値ではない)
+#[derive(Clone, PartialEq, Debug)]
+pub struct Quantity {
+    pub value: f64,
+    pub unit: Unit,
+}
+
+impl Quantity {
+    pub fn new(value: f64, unit: Unit) -> Self {
+        Quantity { value, unit }
+    }
+
+    // 単位の無い数値
+    pub fn scalar(value: f64) -> Self {
+        Quantity::new(value, Unit::scalar())
+    }
+
+    // 3d や 2w のような時間
+    pub fn is_duration(&self) -> bool {
+        self.unit.dim == Dim([0, 0, 1, 0, 0, 0, 0])
+    }
+
+    pub fn si_value(&self) -> f64 {
+        self.value * self.unit.factor
+    }
+
+    pub fn convert(&self, unit: &Unit) -> Result<Quantity, UnitError> {
+        self.check_dim(unit)?;
+        Ok(Quantity::new(self.si_value() / unit.factor, unit.clone()))
+    }
+
+    fn check_dim(&self, unit: &Unit) -> Result<(), UnitError> {
+        if self.unit.dim != unit.dim {
+            return Err(UnitError::DimensionMismatch(
+                describe(&self.unit),
+                describe(unit),
+            ));
+        }
+        Ok(())
+    }
+
+    // 加減算などは次元が一致する必要があり､結果は左辺の単位で表す
+    pub fn add(&self, rhs: &Quantity) -> Result<Quantity, UnitError> {
+        let rhs = rhs.convert(&self.unit).map_err(swap)?;
+        Ok(Quantity::new(self.value + rhs.value, self.unit.clone()))
+    }
+
+    pub fn sub(&self, rhs: &Quantity) -> Result<Quantity, UnitError> {
+        let rhs = rhs.convert(&self.unit).map_err(swap)?;
+        Ok(Quantity::new(self.value - rhs.value, self.unit.clone()))
+    }
+
+    pub fn rem(&self, rhs: &Quantity) -> Result<Quantity, UnitError> {
+        let rhs = rhs.convert(&self.unit).map_err(swap)?;
+        if rhs.value == 0.0 {
+            return Err(UnitError::DivisionByZero);
+        }
+        Ok(Quantity::new(self.value % rhs.value, self.unit.clone()))
+    }
+
+    pub fn mul(&self, rhs: &Quantity) -> Result<Quantity, UnitError> {
+        Ok(Quantity::new(self.value * rhs.value, self.unit.mul(&rhs.unit)?))
+    }
+
+    pub fn div(&self, rhs: &Quantity) -> Result<Quantity, UnitError> {
+        if rhs.value == 0.0 {
+            return Err(UnitError::DivisionByZero);
+        }
+        Ok(Quantity::new(self.value / rhs.value, self.unit.div(&rhs.unit)?))
+    }
+
+    pub fn compare(&self, rhs: &Quantity) -> Result<Ordering, UnitError> {
+        self.check_dim(&rhs.unit)?;
+        Ok(self.si_value().total_cmp(&rhs.si_value()))
+    }
+}
+
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.value, self.unit)
+    }
+}
+
+fn describe(unit: &Unit) -> String {
+    if unit.terms.is_empty() {
+        "dimensionless".to_string()
+    } else {
+        unit.to_string()
+    }
+}
+
+// convertは(変換元, 変換先)の順で返すので､(左辺, 右辺)の順に直す
+fn swap(e: UnitError) -> UnitError {
+    match e {
+        UnitError::DimensionMismatch(rhs, lhs) => UnitError::DimensionMismatch(lhs, rhs),
+        e => e,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn q(value: f64, symbol: &str) -> Quantity {
+        Quantity::new(value, Unit::lookup(symbol).unwrap())
+    }
+
+    #[test]
+    fn test_lookup() {
+        assert_eq!(Unit::lookup("km").unwrap().factor, 1e3);
+        assert_eq!(Unit::lookup("m").unwrap().factor, 1.0);
+        assert_eq!(Unit::lookup("mm").unwrap().factor, 1e-3);
+        assert_eq!(Unit::lookup("kg").unwrap().factor, 1.0);
+        assert_eq!(Unit::lookup("h").unwrap().factor, 3600.0);
+        assert!(Unit::lookup("kmin").is_none());
+        assert!(Unit::lookup("foo").is_none());
+        assert_eq!(Unit::lookup("minute"), Unit::lookup("min"));
+        assert_eq!(Unit::lookup("hour").unwrap().to_string(), "h");
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(q(1.0, "km").add(&q(500.0, "m")).unwrap(), q(1.5, "km"));
+        assert_eq!(
+            q(1.0, "m").add(&q(1.0, "s")),
+            Err(UnitError::DimensionMismatch("m".into(), "s".into()))
+        );
+        let speed = q(100.0, "km").div(&q(2.0, "h")).unwrap();
+        assert_eq!(speed.to_string(), "50 km/h");
+        let force = q(2.0, "kg").mul(&q(3.0, "m")).unwrap().div(&q(1.0, "s").mul(&q(1.0, "s")).unwrap()).unwrap();
+        assert_eq!(force.to_string(), "6 kg*m/s^2");
+        assert_eq!(force.convert(&Unit::lookup("N").unwrap()).unwrap(), q(6.0, "N"));
+    }
+
+    #[test]
+    fn test_convert() {
+        assert_eq!(q(5000.0, "m").convert(&Unit::lookup("km").unwrap()).unwrap(), q(5.0, "km"));
+        assert!(q(1.0, "m").convert(&Unit::lookup("s").unwrap()).is_err());
+        assert!(q(1.0, "m").div(&q(1.0, "m")).unwrap().unit.is_dimensionless());
+    }
+
+    // 指数がi8を超えたらパニックや折り返しではなくエラー
+    #[test]
+    fn test_overflow() {
+        let m = Unit::lookup("m").unwrap();
+        let mut unit = m.clone();
+        for _ in 1..127 {
+            unit = unit.mul(&m).unwrap();
+        }
+        assert_eq!(unit.to_string(), "m^127");
+        assert_eq!(unit.mul(&m), Err(UnitError::Overflow));
+        assert!(m.div(&unit).is_ok());
+        assert_eq!(m.div(&unit).unwrap().div(&unit), Err(UnitError::Overflow));
+    }
+}
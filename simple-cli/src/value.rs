@@ -0,0 +1,114 @@
+use crate::date::Date;
+use crate::interval::Interval;
+use crate::number::Number;
+use crate::parser::{Block, Node};
+use crate::units::{Quantity, Unit};
+use std::fmt;
+use std::mem::size_of;
+
+// スタックに積む値
+#[derive(Clone, PartialEq)]
+pub enum Value {
+    Num(Number),
+    List(Vec<Value>),
+    Quote(Vec<Node>),
+    // 3 m のような単位付きの値
+    Quantity(Quantity),
+    // km -> の km のように､数値に付かなかった単位
+    Unit(Unit),
+    Date(Date),
+    // 3.2+-0.1 のような誤差付きの値
+    Interval(Interval),
+    // "hello" のような文字列
+    Str(String),
+}
+
+impl Value {
+    // リストと文字列が使うヒープの大きさ(バイト)の目安. Limitsのmax_memoryに使う
+    pub fn heap_size(&self) -> usize {
+        match self {
+            Value::List(values) => values.len() * size_of::<Value>() + values.iter().map(Value::heap_size).sum::<usize>(),
+            Value::Quote(body) => body.len() * size_of::<Node>(),
+            Value::Str(s) => s.len(),
+            _ => 0,
+        }
+    }
+
+    // リストの入れ子の深さ. リストでない値は0､[1 2] は1
+    pub fn depth(&self) -> usize {
+        match self {
+            Value::List(values) => 1 + values.iter().map(Value::depth).max().unwrap_or(0),
+            _ => 0,
+        }
+    }
+
+    // 型の不一致エラーで表示する名前
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Num(_) => "number",
+            Value::List(_) => "list",
+            Value::Quote(_) => "quote",
+            Value::Quantity(_) => "quantity",
+            Value::Unit(_) => "unit",
+            Value::Date(_) => "date",
+            Value::Interval(_) => "interval",
+            Value::Str(_) => "string",
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Num(x) => write!(f, "{}", x),
+            Value::List(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    // リストの中の文字列は区切りが分かるように引用符を付ける
+                    match value {
+                        Value::Str(s) => write!(f, "{:?}", s)?,
+                        value => write!(f, "{}", value)?,
+                    }
+                }
+                write!(f, "]")
+            }
+            Value::Quote(nodes) => write!(f, "{}", Block(nodes)),
+            Value::Quantity(q) => write!(f, "{}", q),
+            Value::Unit(unit) => write!(f, "{}", unit),
+            Value::Date(date) => write!(f, "{}", date),
+            Value::Interval(x) => write!(f, "{}", x),
+            Value::Str(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+// verbose表示では文字列に引用符を付ける
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Str(s) => write!(f, "{:?}", s),
+            value => fmt::Display::fmt(value, f),
+        }
+    }
+}
+
+impl From<Number> for Value {
+    fn from(x: Number) -> Self {
+        Value::Num(x)
+    }
+}
+
+impl PartialEq<i64> for Value {
+    fn eq(&self, other: &i64) -> bool {
+        matches!(self, Value::Num(x) if x == other)
+    }
+}
+
+impl PartialEq<f64> for Value {
+    fn eq(&self, other: &f64) -> bool {
+        matches!(self, Value::Num(x) if x == other)
+    }
+}
@@ -0,0 +1,191 @@
+use crate::calculator::RpnCalculator;
+use crate::format::Format;
+use crate::script::Script;
+use anyhow::Result;
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+// rpncalc --watch の実装. ファイルの更新時刻を定期的に調べ､変わったら全行を評価し直して前回との差分を表示する
+// ※ includeしたファイルの更新は検出しない(元のファイルを保存し直せば評価し直す)
+
+const INTERVAL: Duration = Duration::from_millis(500);
+
+// 1行分の評価結果
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    // 行番号. includeしたファイルの行は "ファイル名:行番号"
+    pub label: String,
+    pub text: String,
+    // 表示する値かエラーメッセージ. "3 =x" のような行は空
+    pub result: String,
+    pub error: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Status {
+    Same,
+    // 前回の結果
+    Changed(String),
+    New,
+}
+
+// 変数などが前回の評価から持ち越されないよう､評価するたびに計算機を作り直す
+pub fn run<F>(path: &Path, make: F, format: &Format) -> Result<()>
+where
+    F: Fn() -> Result<RpnCalculator>,
+{
+    let color = io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none();
+    let mut prev: Option<Vec<Entry>> = None;
+    let mut last = None;
+    loop {
+        // 保存中でファイルが無い間は前回の状態のまま待つ
+        let stamp = fs::metadata(path).and_then(|m| Ok((m.modified()?, m.len()))).ok();
+        if stamp.is_some() && stamp != last {
+            last = stamp;
+            match make().and_then(|calcurator| evaluate(path, &calcurator, format)) {
+                Ok(entries) => {
+                    let (statuses, removed) = match &prev {
+                        Some(prev) => diff(prev, &entries),
+                        None => (entries.iter().map(|_| Status::Same).collect(), 0),
+                    };
+                    print!("{}", render(&entries, &statuses, removed, color));
+                    prev = Some(entries);
+                }
+                Err(e) => println!("Error: {:#}", e),
+            }
+            println!("-- watching {} (Ctrl-C to stop)", path.display());
+            io::stdout().flush()?;
+        }
+        thread::sleep(INTERVAL);
+    }
+}
+
+pub fn evaluate(path: &Path, calcurator: &RpnCalculator, format: &Format) -> Result<Vec<Entry>> {
+    let main = path.display().to_string();
+    let mut entries = Vec::new();
+    for line in Script::open(path)? {
+        let line = line?;
+        let label = if line.source == main {
+            line.number.to_string()
+        } else {
+            format!("{}:{}", line.source, line.number)
+        };
+        let (result, error) = match calcurator.eval_line(&line.text) {
+            Ok(values) => (format.values(&values), false),
+            Err(e) => (format.error(&e), true),
+        };
+        entries.push(Entry {
+            label,
+            text: line.text,
+            result,
+            error,
+        });
+    }
+    Ok(entries)
+}
+
+// 式の文字列で前回の行と対応付ける. 同じ式が何度も出てくる場合は出現順に対応させる
+// 今回の各行の状態と､前回あって今回なくなった行の数を返す
+pub fn diff(prev: &[Entry], cur: &[Entry]) -> (Vec<Status>, usize) {
+    let mut old: HashMap<&str, VecDeque<&Entry>> = HashMap::new();
+    for entry in prev {
+        old.entry(&entry.text).or_default().push_back(entry);
+    }
+    let statuses = cur
+        .iter()
+        .map(|entry| match old.get_mut(entry.text.as_str()).and_then(VecDeque::pop_front) {
+            Some(p) if p.result == entry.result => Status::Same,
+            Some(p) => Status::Changed(p.result.clone()),
+            None => Status::New,
+        })
+        .collect();
+    let removed = old.values().map(VecDeque::len).sum();
+    (statuses, removed)
+}
+
+// 変わった行には~､増えた行には+を付ける. colorなら変わった結果を黄色､エラーを赤で強調する
+pub fn render(entries: &[Entry], statuses: &[Status], removed: usize, color: bool) -> String {
+    let paint = |code: &str, s: &str| {
+        if color {
+            format!("\x1b[{}m{}\x1b[0m", code, s)
+        } else {
+            s.to_string()
+        }
+    };
+    let label_width = entries.iter().map(|e| e.label.len()).max().unwrap_or(0);
+    let text_width = entries.iter().map(|e| e.text.chars().count()).max().unwrap_or(0).min(40);
+    let mut out = String::new();
+    for (entry, status) in entries.iter().zip(statuses) {
+        let mark = match status {
+            Status::Same => " ",
+            Status::Changed(_) => "~",
+            Status::New => "+",
+        };
+        let mut result = if entry.error {
+            paint("31", &entry.result)
+        } else {
+            entry.result.clone()
+        };
+        if let Status::Changed(old) = status {
+            result = format!("{}  (was {})", paint("1;33", &entry.result), old);
+        }
+        let line = format!(
+            "{} {:>lw$}  {:<tw$}  {}",
+            mark,
+            entry.label,
+            entry.text,
+            result,
+            lw = label_width,
+            tw = text_width
+        );
+        out += line.trim_end();
+        out.push('\n');
+    }
+    let changed = statuses.iter().filter(|s| matches!(s, Status::Changed(_))).count();
+    let added = statuses.iter().filter(|s| **s == Status::New).count();
+    out += &format!("{} changed, {} new, {} removed\n", changed, added, removed);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(label: &str, text: &str, result: &str) -> Entry {
+        Entry {
+            label: label.to_string(),
+            text: text.to_string(),
+            result: result.to_string(),
+            error: false,
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let prev = vec![entry("1", "10 =x", ""), entry("2", "x 2 *", "20"), entry("3", "1 1 +", "2")];
+        let cur = vec![entry("1", "12 =x", ""), entry("2", "x 2 *", "24"), entry("3", "3 4 *", "12")];
+        let (statuses, removed) = diff(&prev, &cur);
+        assert_eq!(statuses, vec![Status::New, Status::Changed("20".into()), Status::New]);
+        assert_eq!(removed, 2);
+        assert_eq!(
+            render(&cur, &statuses, removed, false),
+            "+ 1  12 =x\n~ 2  x 2 *  24  (was 20)\n+ 3  3 4 *  12\n1 changed, 2 new, 2 removed\n"
+        );
+        let (statuses, removed) = diff(&cur, &cur);
+        assert!(statuses.iter().all(|s| *s == Status::Same) && removed == 0);
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let calcurator = RpnCalculator::new(false);
+        let entries = evaluate(Path::new("assets/golden/lexer.rpn"), &calcurator, &Format::default()).unwrap();
+        assert_eq!(entries[0], entry("2", "2 3+", "5"));
+        let last = entries.last().unwrap();
+        assert!(last.error && last.result.contains("unterminated"), "{:?}", last);
+    }
+}